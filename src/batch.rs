@@ -0,0 +1,45 @@
+use crate::{CustomError, Error};
+
+use datafusion::arrow::array::{ArrayRef, AsArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, Float64Type};
+use datafusion::arrow::record_batch::RecordBatch;
+
+/// バッチからカラムを名前で取り出す
+fn column_by_name<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a ArrayRef, Error> {
+    batch.column_by_name(name).ok_or_else(|| {
+        Error::DataFrameError(
+            CustomError::msg(format!("The column `{name}` is not found in the result.")).into(),
+        )
+    })
+}
+
+/// 数値のカラムをf64として取り出す
+pub(crate) fn f64_column(batches: &[RecordBatch], name: &str) -> Result<Vec<Option<f64>>, Error> {
+    let mut values = Vec::new();
+    for batch in batches.iter() {
+        let array = cast(column_by_name(batch, name)?, &DataType::Float64)
+            .map_err(|e| Error::DataFrameError(e.into()))?;
+        values.extend(array.as_primitive::<Float64Type>().iter());
+    }
+    Ok(values)
+}
+
+/// カラムを文字列として取り出す
+pub(crate) fn string_column(
+    batches: &[RecordBatch],
+    name: &str,
+) -> Result<Vec<Option<String>>, Error> {
+    let mut values = Vec::new();
+    for batch in batches.iter() {
+        let array = cast(column_by_name(batch, name)?, &DataType::Utf8)
+            .map_err(|e| Error::DataFrameError(e.into()))?;
+        values.extend(
+            array
+                .as_string::<i32>()
+                .iter()
+                .map(|value| value.map(|value| value.to_string())),
+        );
+    }
+    Ok(values)
+}
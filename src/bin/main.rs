@@ -1,10 +1,42 @@
-use coca_frequency_list::{query::simple_query, CustomError, Error, MatchType, SheetType};
+use coca_frequency_list::cluster::{cluster_lemmas, ClusterOutput};
+use coca_frequency_list::compare::{compare_lemmas, CompareTarget};
+use coca_frequency_list::config::{Config, DEFAULT_CONFIG_PATH};
+use coca_frequency_list::correlation::genre_correlations;
+use coca_frequency_list::dispersion::{bursty, RankMode};
+use coca_frequency_list::distribution::{Distance, DistributionSource};
+use coca_frequency_list::document::{read_directory, read_document, InputFormat};
+use coca_frequency_list::export::{anki_cards, load_forms, write_anki, CardTemplate};
+use coca_frequency_list::external::ExternalTable;
+use coca_frequency_list::extract::{extract, parse_references, ExtractCriteria};
+use coca_frequency_list::gloss::{Glossary, GLOSS_TABLE_NAME};
+use coca_frequency_list::keyness::{keyness, Reference};
+use coca_frequency_list::known::{KnownList, DEFAULT_KNOWN_PATH, KNOWN_TABLE_NAME};
+use coca_frequency_list::lemmatizer::Lemmatizer;
+use coca_frequency_list::profile::RegisterProfile;
+use coca_frequency_list::sample::{Sample, SampleWeight};
+use coca_frequency_list::similarity::similar_lemmas;
+use coca_frequency_list::sort::{parse_sort_keys, SortKey};
+use coca_frequency_list::stats::{sheet_stats, StatsGroup};
+use coca_frequency_list::subgenre::{
+    compare_within_genre, over_represented, register_subgenre_metadata,
+    registered_subgenre_columns, rollup, SUBGENRE_TABLE_NAME,
+};
+use coca_frequency_list::top::Grouping;
+use coca_frequency_list::views::{register_lemma_genre_view, register_lemma_subgenre_view};
+use coca_frequency_list::vocabulary::{
+    band_coverage, coverage_report, lemmatize_segments, off_band_words, parse_percent, study_plan,
+};
+use coca_frequency_list::vst::{generate_test, score_test};
+use coca_frequency_list::{
+    query::{simple_query, QueryOptions},
+    BandRange, CustomError, Error, Genre, MatchType, SheetType,
+};
 
 use std::path::{Path, PathBuf};
 
 use clap::{Args, Parser, Subcommand};
 use datafusion::dataframe::DataFrameWriteOptions;
-use datafusion::prelude::SessionContext;
+use datafusion::prelude::{DataFrame, SessionContext};
 
 /// query the coca frequency list
 #[derive(Parser, Debug)]
@@ -41,6 +73,18 @@ enum Commands {
         #[arg(long)]
         sheets: Option<String>,
 
+        /// register a dictionary file as the glosses table (the glossary in the config file if no path is given)
+        #[arg(long)]
+        gloss: Option<Option<PathBuf>>,
+
+        /// register the known-words store as the known table
+        #[arg(long)]
+        known: bool,
+
+        /// register a csv, tsv, parquet or json file as a table (e.g. cmu=cmudict.csv)
+        #[arg(long = "table")]
+        tables: Vec<String>,
+
         #[command(flatten)]
         common: Common,
     },
@@ -62,10 +106,103 @@ enum Commands {
         #[arg(long)]
         pos: Option<String>,
 
-        /// column name for sorting
-        #[arg(long)]
+        /// filter expression (e.g. "freq > 5000 and acad > spok and PoS in (n, j)")
+        #[arg(long = "where")]
+        filter: Option<String>,
+
+        /// column name for sorting in descending order
+        #[arg(long, conflicts_with = "sort")]
         sorted: Option<String>,
 
+        /// sort keys (e.g. "PoS asc, freq desc nulls last")
+        #[arg(long)]
+        sort: Option<String>,
+
+        /// number of rows per group
+        #[arg(long, requires = "per")]
+        top: Option<usize>,
+
+        /// group for top-N queries: PoS, genre, band or any column name
+        #[arg(long, requires = "top")]
+        per: Option<String>,
+
+        /// register profile for re-ranking: genre weights (e.g. spok=0.6,fic=0.3,news=0.1) or a saved profile name
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// save the profile with the name into the config file
+        #[arg(long, requires = "profile")]
+        save_profile: Option<String>,
+
+        /// rank by frequency combined with dispersion: freq, usage (freq * disp) or adjusted (adjusted frequency)
+        #[arg(long)]
+        rank_mode: Option<String>,
+
+        /// minimum dispersion (disp) for core vocabulary
+        #[arg(long)]
+        min_disp: Option<f64>,
+
+        /// add subset_rank column renumbered within the filtered rows
+        #[arg(long)]
+        rerank: bool,
+
+        /// rerank by the column (e.g. a genre like acad) in descending order
+        #[arg(long)]
+        rerank_by: Option<String>,
+
+        /// exclude lemmas in the known-words store
+        #[arg(long)]
+        unknown: bool,
+
+        /// add the lemmas of the result to the known-words store
+        #[arg(long)]
+        mark_known: bool,
+
+        /// attach glosses from a dictionary file (EJDict .txt/.tsv, JMdict .xml or .csv), the glossary in the config file by default
+        #[arg(long)]
+        gloss: Option<Option<PathBuf>>,
+
+        /// sheet number of frequency data
+        #[arg(long)]
+        sheet: Option<usize>,
+
+        /// additional columns
+        #[arg(long)]
+        columns: Option<String>,
+
+        /// get all columns
+        #[arg(long)]
+        all: bool,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// weighted random sample of rows
+    Sample {
+        /// number of rows
+        #[arg(long, default_value_t = 100)]
+        n: usize,
+
+        /// weight of rows: freq, uniform or log (ln(1 + freq))
+        #[arg(long, default_value = "freq")]
+        weight: String,
+
+        /// seed for sampling
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        /// filter expression (e.g. "rank <= 5000 and PoS in (n, j)")
+        #[arg(long = "where")]
+        filter: Option<String>,
+
+        /// exclude lemmas in the known-words store
+        #[arg(long)]
+        unknown: bool,
+
         /// sheet number of frequency data
         #[arg(long)]
         sheet: Option<usize>,
@@ -78,119 +215,1295 @@ enum Commands {
         #[arg(long)]
         all: bool,
 
+        #[command(flatten)]
+        common: Common,
+    },
+    /// keyness of lemmas between genres
+    Keyness {
+        /// target genre (blog, web, TVM, spok, fic, mag, news, acad)
+        #[arg(long)]
+        target: String,
+
+        /// reference genre or "rest" for all the other genres
+        #[arg(long, default_value = "rest")]
+        reference: String,
+
+        /// significance level for log-likelihood (0.05, 0.01, 0.001 or 0.0001)
+        #[arg(long)]
+        significance: Option<f64>,
+
+        /// minimum absolute log ratio
+        #[arg(long)]
+        min_log_ratio: Option<f64>,
+
+        /// get lemmas under-represented in the target genre
+        #[arg(long)]
+        negative: bool,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// queries on the subgenre hierarchy
+    Subgenre {
+        #[command(subcommand)]
+        command: SubgenreCommands,
+    },
+    /// lemmas whose frequency is driven by few texts
+    Bursty {
+        /// number of lemmas from the top of the rank
+        #[arg(long, default_value_t = 5000)]
+        top: usize,
+
+        /// maximum dispersion (disp) to be flagged
+        #[arg(long, default_value_t = 0.8)]
+        max_disp: f64,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// genre-specific vocabulary list like AWL (academic, spoken, fiction, news, ...)
+    Extract {
+        /// target genre
+        genre: String,
+
+        /// general genres to compare with (e.g. "fic,mag,news"), all the other genres by default
+        #[arg(long)]
+        reference: Option<String>,
+
+        /// minimum ratio of per-million frequencies between the target and the reference
+        #[arg(long, default_value_t = 1.5)]
+        min_ratio: f64,
+
+        /// minimum per-million frequency in the target genre
+        #[arg(long)]
+        min_per_mil: Option<f64>,
+
+        /// minimum share of the target subgenres where the lemma is dispersed
+        #[arg(long, default_value_t = 0.7)]
+        min_coverage: f64,
+
+        /// share of the expected frequency to count a subgenre as covered
+        #[arg(long, default_value_t = 0.2)]
+        expected_share: f64,
+
+        /// skip the subgenre dispersion check
+        #[arg(long)]
+        no_subgenre: bool,
+
+        /// general bands to exclude (e.g. "1..2") or "none"
+        #[arg(long, default_value = "1")]
+        exclude_bands: String,
+
+        /// minimum dispersion (disp) over the whole corpus
+        #[arg(long)]
+        min_disp: Option<f64>,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// rank correlations between genres and lemmas whose rank varies across genres
+    Correlation {
+        /// number of lemmas from the top of the rank
+        #[arg(long, default_value_t = 2000)]
+        top: usize,
+
+        /// genres to compare (e.g. "spok,fic,acad"), all the genres by default
+        #[arg(long)]
+        genres: Option<String>,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        /// number of lemmas in the list of variable ranks
+        #[arg(long, default_value_t = 50)]
+        variable: usize,
+
+        /// path for saving the results as csv files (suffixed with section names)
+        #[arg(long)]
+        dist_path: Option<PathBuf>,
+    },
+    /// compare genre and subgenre distributions of two lemmas
+    Compare {
+        /// first lemma
+        lemma_a: String,
+
+        /// second lemma
+        lemma_b: String,
+
+        /// part of speech(pos) of both lemmas (defaults to the most frequent ones)
+        #[arg(long)]
+        pos: Option<String>,
+
+        /// part of speech(pos) of the first lemma
+        #[arg(long)]
+        pos_a: Option<String>,
+
+        /// part of speech(pos) of the second lemma
+        #[arg(long)]
+        pos_b: Option<String>,
+
+        /// path for saving the results as csv files (suffixed with section names)
+        #[arg(long)]
+        dist_path: Option<PathBuf>,
+    },
+    /// off-band words in a subtitle, book or text file for pre-study
+    Profile {
+        /// input file (srt, vtt, ass, html, epub or plain text)
+        #[arg(long)]
+        input: PathBuf,
+
+        /// input format, inferred from the extension by default
+        #[arg(long)]
+        format: Option<String>,
+
+        /// number of top lemmas regarded as known
+        #[arg(long, default_value_t = 3000)]
+        known: usize,
+
+        /// include words not in the frequency list (names, typos, ...)
+        #[arg(long)]
+        unlisted: bool,
+
+        /// report band coverage per chapter and overall instead of off-band words
+        #[arg(long)]
+        coverage: bool,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// text coverage by the top lemmas a learner knows
+    Coverage {
+        /// input file (srt, vtt, ass, html, epub or plain text)
+        input: PathBuf,
+
+        /// input format, inferred from the extension by default
+        #[arg(long)]
+        format: Option<String>,
+
+        /// number of top lemmas regarded as known
+        #[arg(long, default_value_t = 3000)]
+        known: usize,
+
+        /// step of known lemmas for the coverage curve
+        #[arg(long, default_value_t = 1000)]
+        step: usize,
+
+        /// maximum number of known lemmas for the coverage curve
+        #[arg(long, default_value_t = 20000)]
+        max_known: usize,
+
+        /// count words not in the frequency list (names, ...) as known
+        #[arg(long)]
+        unlisted_known: bool,
+
+        /// number of the most frequent unknown lemmas
+        #[arg(long, default_value_t = 50)]
+        top_unknown: usize,
+
+        /// path for saving the results as csv files (suffixed with section names)
+        #[arg(long)]
+        dist_path: Option<PathBuf>,
+    },
+    /// ordered study list to reach the target coverage of a text collection
+    Plan {
+        /// directory of target texts (srt, vtt, ass, html, epub or txt files)
+        directory: PathBuf,
+
+        /// target coverage as a percentage with `%` ("98%") or a fraction between 0 and 1 ("0.98")
+        #[arg(long, default_value = "98%")]
+        target: String,
+
+        /// number of top lemmas already known
+        #[arg(long, default_value_t = 0)]
+        known: usize,
+
+        /// file of known lemmas, one "lemma" or "lemma,PoS" per line
+        #[arg(long)]
+        known_list: Option<PathBuf>,
+
+        /// count words not in the frequency list (names, ...) as known
+        #[arg(long)]
+        unlisted_known: bool,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// manage the known-words store
+    Known {
+        #[command(subcommand)]
+        command: KnownCommands,
+    },
+    /// export lemmas for flashcards
+    Export {
+        #[command(subcommand)]
+        command: ExportCommands,
+    },
+    /// vocabulary size test
+    Vst {
+        #[command(subcommand)]
+        command: VstCommands,
+    },
+    /// summary statistics of the sheets
+    Stats {
+        /// sheet numbers of frequency data
+        #[arg(long)]
+        sheets: Option<String>,
+
+        /// group rows by PoS or band
+        #[arg(long)]
+        group_by: Option<String>,
+
+        /// path for saving the results as csv files (suffixed with sheet and section names)
+        #[arg(long)]
+        dist_path: Option<PathBuf>,
+    },
+    /// lemmas with similar genre distributions
+    Similar {
+        /// lemma to compare
+        lemma: String,
+
+        /// part of speech(pos) of the lemma (defaults to the most frequent one)
+        #[arg(long)]
+        target_pos: Option<String>,
+
+        /// filtering candidates by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        /// filtering candidates by rank bands (e.g. "1..5")
+        #[arg(long)]
+        bands: Option<String>,
+
+        /// distance between distributions (cosine or js)
+        #[arg(long, default_value = "cosine")]
+        distance: String,
+
+        /// use subgenre distributions instead of genre distributions
+        #[arg(long)]
+        subgenre: bool,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// cluster lemmas by genre distributions with k-means
+    Cluster {
+        /// number of clusters
+        #[arg(long, default_value_t = 8)]
+        k: usize,
+
+        /// number of lemmas from the top of the rank
+        #[arg(long, default_value_t = 5000)]
+        top: usize,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        /// use subgenre distributions instead of genre distributions
+        #[arg(long)]
+        subgenre: bool,
+
+        /// output assignments or centroids
+        #[arg(long, default_value = "assignments")]
+        output: String,
+
+        /// maximum number of iterations
+        #[arg(long, default_value_t = 100)]
+        max_iterations: usize,
+
+        /// seed for the initial centroids
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+
         #[command(flatten)]
         common: Common,
     },
 }
 
-/// データソースの登録
-async fn register_data(ctx: &SessionContext, sheet_type: SheetType) -> Result<(), Error> {
-    let data_path = format!("./data/{}", sheet_type.file_name());
-    if !PathBuf::from(data_path.clone()).is_file() {
-        Err(Error::IOError(CustomError::msg(
-            r#"
-The data source files cannot be found. Please download xlsx file from the official website: https://www.wordfrequency.info/samples.asp and locate it as "./data/wordFrequency.xlsx". And then run command `just build`.
-        "#,
-        ).into()))?;
-    }
-    ctx.register_csv(&sheet_type.table_name(), &data_path, Default::default())
-        .await?;
+/// knownコマンドのサブコマンド
+#[derive(Subcommand, Debug)]
+enum KnownCommands {
+    /// import a list of known lemmas ("lemma" or "lemma,PoS" per line)
+    Import {
+        /// list file
+        file: PathBuf,
+    },
+    /// add known lemmas
+    Add {
+        /// lemmas separated by commas
+        lemmas: String,
 
-    Ok(())
+        /// part of speech(pos) of the lemmas, any pos by default
+        #[arg(long)]
+        pos: Option<String>,
+    },
+    /// remove known lemmas
+    Remove {
+        /// lemmas separated by commas
+        lemmas: String,
+
+        /// part of speech(pos) of the lemmas, all pos by default
+        #[arg(long)]
+        pos: Option<String>,
+    },
+    /// list known lemmas
+    List {
+        #[command(flatten)]
+        common: Common,
+    },
 }
 
-/// sqlコマンド
-async fn sql_command(
-    ctx: &SessionContext,
-    sql: &str,
-    skip: Option<usize>,
-    limit: Option<usize>,
-    dist_path: Option<&Path>,
-) -> Result<(), Error> {
-    let mut df = ctx.sql(sql).await?;
+/// exportコマンドのサブコマンド
+#[derive(Subcommand, Debug)]
+enum ExportCommands {
+    /// tab-separated file for importing into Anki
+    Anki {
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
 
-    if skip.is_some() || limit.is_some() {
-        df = df.limit(skip.unwrap_or(0), limit)?;
-    }
+        /// filter expression (e.g. "freq > 5000 and PoS in (n, j)")
+        #[arg(long = "where")]
+        filter: Option<String>,
 
-    match dist_path {
-        Some(path) => {
-            let opt = DataFrameWriteOptions::new().with_single_file_output(true);
+        /// sort keys (e.g. "PoS asc, freq desc nulls last")
+        #[arg(long)]
+        sort: Option<String>,
 
-            df.write_csv(path.to_str().unwrap(), opt, None).await?;
-        }
-        None => {
-            df.show().await?;
-        }
-    }
-    Ok(())
-}
+        /// exclude lemmas in the known-words store
+        #[arg(long)]
+        unknown: bool,
 
-/// queryコマンド
-async fn query_command(
-    ctx: &SessionContext,
-    sheet_type: SheetType,
-    words: Option<&[String]>,
-    prefix: bool,
-    suffix: bool,
-    pos_list: Option<&[String]>,
-    sorted_column: Option<&str>,
-    skip: Option<usize>,
-    limit: Option<usize>,
-    columns: Option<&[String]>,
-    all: bool,
+        /// saved card template name
+        #[arg(long)]
+        template: Option<String>,
+
+        /// template of the front side (fields: lemma, PoS, rank, band, forms, gloss)
+        #[arg(long)]
+        front: Option<String>,
+
+        /// template of the back side (fields: lemma, PoS, rank, band, forms, gloss)
+        #[arg(long)]
+        back: Option<String>,
+
+        /// save the card template with the name into the config file
+        #[arg(long)]
+        save_template: Option<String>,
+
+        /// deck name
+        #[arg(long)]
+        deck: Option<String>,
+
+        /// attach glosses from a dictionary file (EJDict .txt/.tsv, JMdict .xml or .csv), the glossary in the config file by default
+        #[arg(long)]
+        gloss: Option<Option<PathBuf>>,
+
+        #[command(flatten)]
+        common: Common,
+    },
+}
+
+/// vstコマンドのサブコマンド
+#[derive(Subcommand, Debug)]
+enum VstCommands {
+    /// generate a test sheet sampling lemmas from each rank band
+    Generate {
+        /// number of items in each band
+        #[arg(long, default_value_t = 10)]
+        per_band: usize,
+
+        /// rank bands to test (e.g. "1..14")
+        #[arg(long, default_value = "1..14")]
+        bands: String,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        /// seed for sampling
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// estimate the vocabulary size from a test sheet with responses
+    Score {
+        /// test sheet with the response column filled (1: known, 0: unknown)
+        responses: PathBuf,
+
+        /// path for saving the results as csv files (suffixed with section names)
+        #[arg(long)]
+        dist_path: Option<PathBuf>,
+    },
+}
+
+/// subgenreコマンドのサブコマンド
+#[derive(Subcommand, Debug)]
+enum SubgenreCommands {
+    /// mapping of subgenre columns to their genres
+    Genres {
+        #[command(flatten)]
+        common: Common,
+    },
+    /// aggregate subgenre columns into genres
+    Rollup {
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// compare subgenres within a genre
+    Compare {
+        /// genre whose subgenres are compared
+        #[arg(long)]
+        genre: String,
+
+        /// lemma to compare
+        #[arg(long)]
+        lemma: Option<String>,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        #[command(flatten)]
+        common: Common,
+    },
+    /// subgenres where the lemma is over-represented
+    Over {
+        /// lemma to search
+        lemma: String,
+
+        /// filtering by part of speech(pos)
+        #[arg(long)]
+        pos: Option<String>,
+
+        /// minimum ratio of observed to expected frequency
+        #[arg(long, default_value_t = 1.5)]
+        min_ratio: f64,
+
+        #[command(flatten)]
+        common: Common,
+    },
+}
+
+/// シート番号のリストをパースする
+fn parse_sheets(sheets: Option<String>) -> Result<Vec<SheetType>, Error> {
+    let sheets = sheets
+        .unwrap_or("1".to_string())
+        .split(",")
+        .map(|sheet| sheet.parse())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|e| {
+            Error::ArgError(
+                CustomError::new("sheets must be integer or list of integer.", e).into(),
+            )
+        })?;
+
+    sheets
+        .into_iter()
+        .map(|sheet_number| {
+            TryInto::<SheetType>::try_into(sheet_number).map_err(|e| Error::ArgError(e.into()))
+        })
+        .collect()
+}
+
+/// レジスタープロファイルを重みの指定か設定ファイルの名前から取得する．
+///
+/// `save_as`が指定された場合はその名前で設定ファイルに保存する．
+fn load_profile(profile: &str, save_as: Option<&str>) -> Result<RegisterProfile, Error> {
+    let config_path = PathBuf::from(DEFAULT_CONFIG_PATH);
+    let mut config = Config::load(&config_path)?;
+
+    let register_profile = if profile.contains('=') {
+        RegisterProfile::parse(profile)
+    } else {
+        match config.profiles.get(profile) {
+            Some(weights) => RegisterProfile::from_weights(weights),
+            None => {
+                return Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "The profile `{profile}` is not found in {DEFAULT_CONFIG_PATH}. Saved profiles: {:?}",
+                        config.profiles.keys().collect::<Vec<_>>()
+                    ))
+                    .into(),
+                ));
+            }
+        }
+    }
+    .map_err(|e| Error::ArgError(e.into()))?;
+
+    if let Some(name) = save_as {
+        config
+            .profiles
+            .insert(name.to_string(), register_profile.to_weights());
+        config.save(&config_path)?;
+    }
+
+    Ok(register_profile)
+}
+
+/// 名前付きのテンプレートを読み込み，表面や裏面の指定で上書きする．
+/// 名前が指定されていなければデフォルトのテンプレートを用いる．
+fn load_template(
+    template: Option<&str>,
+    front: Option<&str>,
+    back: Option<&str>,
+    save_as: Option<&str>,
+) -> Result<CardTemplate, Error> {
+    let config_path = PathBuf::from(DEFAULT_CONFIG_PATH);
+    let mut config = Config::load(&config_path)?;
+
+    let base = match template {
+        Some(name) => match config.templates.get(name) {
+            Some(template) => template.clone(),
+            None => {
+                return Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "The template `{name}` is not found in {DEFAULT_CONFIG_PATH}. Saved templates: {:?}",
+                        config.templates.keys().collect::<Vec<_>>()
+                    ))
+                    .into(),
+                ));
+            }
+        },
+        None => CardTemplate::default(),
+    };
+    let card_template = CardTemplate::new(front.unwrap_or(&base.front), back.unwrap_or(&base.back))
+        .map_err(|e| Error::ArgError(e.into()))?;
+
+    if let Some(name) = save_as {
+        config
+            .templates
+            .insert(name.to_string(), card_template.clone());
+        config.save(&config_path)?;
+    }
+
+    Ok(card_template)
+}
+
+/// 訳語の辞書を読み込む．パスが無ければ設定ファイルの辞書を用いる．
+fn load_glossary(path: Option<PathBuf>) -> Result<Glossary, Error> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let config = Config::load(&PathBuf::from(DEFAULT_CONFIG_PATH))?;
+            match config.glossary {
+                Some(path) => PathBuf::from(path),
+                None => Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "Specify a dictionary file or set `glossary` in {DEFAULT_CONFIG_PATH}."
+                    ))
+                    .into(),
+                ))?,
+            }
+        }
+    };
+    Glossary::read(&path, None)
+}
+
+/// sqlで使う外部のテーブル．設定ファイルのテーブルに引数のテーブルを加え，
+/// 同じ名前のテーブルは引数の指定で置き換える．
+fn external_tables(tables: &[String]) -> Result<Vec<ExternalTable>, Error> {
+    let config = Config::load(&PathBuf::from(DEFAULT_CONFIG_PATH))?;
+    let mut external_tables = config
+        .tables
+        .iter()
+        .map(|(name, path)| ExternalTable::new(name, path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::ConfigError(e.into()))?;
+
+    for table in tables.iter() {
+        let table =
+            ExternalTable::try_from(table.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+        external_tables.retain(|current| current.name != table.name);
+        external_tables.push(table);
+    }
+    Ok(external_tables)
+}
+
+/// 既知語のストアのパス．設定ファイルで変更できる．
+fn known_path() -> Result<PathBuf, Error> {
+    let config = Config::load(&PathBuf::from(DEFAULT_CONFIG_PATH))?;
+    Ok(PathBuf::from(
+        config.known_words.unwrap_or(DEFAULT_KNOWN_PATH.to_string()),
+    ))
+}
+
+/// 結果をcsvファイルに保存するか標準出力に表示する
+async fn write_df(df: DataFrame, dist_path: Option<&Path>) -> Result<(), Error> {
+    match dist_path {
+        Some(path) => {
+            let opt = DataFrameWriteOptions::new().with_single_file_output(true);
+
+            df.write_csv(path.to_str().unwrap(), opt, None).await?;
+        }
+        None => {
+            df.show().await?;
+        }
+    }
+    Ok(())
+}
+
+/// データソースの登録
+async fn register_data(ctx: &SessionContext, sheet_type: SheetType) -> Result<(), Error> {
+    let data_path = format!("./data/{}", sheet_type.file_name());
+    if !PathBuf::from(data_path.clone()).is_file() {
+        Err(Error::IOError(CustomError::msg(
+            r#"
+The data source files cannot be found. Please download xlsx file from the official website: https://www.wordfrequency.info/samples.asp and locate it as "./data/wordFrequency.xlsx". And then run command `just build`.
+        "#,
+        ).into()))?;
+    }
+    ctx.register_csv(&sheet_type.table_name(), &data_path, Default::default())
+        .await?;
+
+    // 縦持ちのビューとサブジャンルとジャンルの対応も登録する
+    match sheet_type {
+        SheetType::First => {
+            register_lemma_genre_view(ctx).await?;
+        }
+        SheetType::Second => {
+            register_subgenre_metadata(ctx).await?;
+            register_lemma_subgenre_view(ctx).await?;
+        }
+        SheetType::Third | SheetType::Fourth => {}
+    }
+
+    Ok(())
+}
+
+/// sqlコマンド
+async fn sql_command(
+    ctx: &SessionContext,
+    sql: &str,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    let mut df = ctx.sql(sql).await?;
+
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path).await
+}
+
+/// 保存先のファイル名にセクション名を付ける
+fn section_path(path: &Path, section: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    path.with_file_name(format!("{stem}_{section}{extension}"))
+}
+
+/// セクションごとに出力する．保存先があればセクション名を付けたファイルに分け，
+/// 無ければ`[section]`の見出しに続けて標準出力に表示する．
+async fn write_sections(
+    sections: Vec<(String, DataFrame)>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    for (section, df) in sections {
+        match dist_path {
+            // 例: compare.csv -> compare_genres.csv
+            Some(path) => write_df(df, Some(&section_path(path, &section))).await?,
+            None => {
+                println!("[{section}]");
+                write_df(df, None).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// statsコマンド
+async fn stats_command(
+    ctx: &SessionContext,
+    sheet_types: &[SheetType],
+    group_by: Option<StatsGroup>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    for sheet_type in sheet_types.iter() {
+        // 例: stats.csv -> stats_lemmas_summary.csv
+        let sections = sheet_stats(ctx, *sheet_type, group_by)
+            .await?
+            .into_iter()
+            .map(|(section, df)| (format!("{}_{section}", sheet_type.table_name()), df))
+            .collect();
+        write_sections(sections, dist_path).await?;
+    }
+    Ok(())
+}
+
+/// correlationコマンド
+async fn correlation_command(
+    ctx: &SessionContext,
+    top: usize,
+    genres: Option<&[Genre]>,
+    pos_list: Option<&[String]>,
+    variable: usize,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    write_sections(
+        genre_correlations(ctx, top, genres, pos_list, variable).await?,
+        dist_path,
+    )
+    .await
+}
+
+/// compareコマンド
+async fn compare_command(
+    ctx: &SessionContext,
+    a: &CompareTarget,
+    b: &CompareTarget,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    for target in [a, b] {
+        if let Some(pos) = target.pos.as_ref() {
+            check_pos_list(&[pos.clone()])?;
+        }
+    }
+
+    write_sections(compare_lemmas(ctx, a, b).await?, dist_path).await
+}
+
+/// profileコマンド
+async fn profile_command(
+    ctx: &SessionContext,
+    input: &Path,
+    format: Option<InputFormat>,
+    known: usize,
+    unlisted: bool,
+    coverage: bool,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    let segments = read_document(input, format)?;
+    let lemmatizer = Lemmatizer::load(ctx).await?;
+    let occurrences = lemmatize_segments(&lemmatizer, &segments);
+
+    let mut df = if coverage {
+        band_coverage(ctx, &segments, &occurrences)?
+    } else {
+        off_band_words(ctx, &segments, &occurrences, known, unlisted)?
+    };
+
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path).await
+}
+
+/// coverageコマンド
+async fn coverage_command(
+    ctx: &SessionContext,
+    input: &Path,
+    format: Option<InputFormat>,
+    known: usize,
+    step: usize,
+    max_known: usize,
+    unlisted_known: bool,
+    top_unknown: usize,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    if step == 0 {
+        Err(Error::ArgError(
+            CustomError::msg("The step of the coverage curve must be positive.").into(),
+        ))?;
+    }
+
+    let segments = read_document(input, format)?;
+    let lemmatizer = Lemmatizer::load(ctx).await?;
+    let occurrences = lemmatize_segments(&lemmatizer, &segments);
+
+    let sections = coverage_report(
+        ctx,
+        &occurrences,
+        known,
+        step,
+        max_known,
+        unlisted_known,
+        top_unknown,
+    )?;
+    write_sections(sections, dist_path).await
+}
+
+/// planコマンド
+async fn plan_command(
+    ctx: &SessionContext,
+    directory: &Path,
+    target: f64,
+    known: usize,
+    known_list: Option<&KnownList>,
+    unlisted_known: bool,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    let documents = read_directory(directory)?;
+    if documents.is_empty() {
+        Err(Error::ArgError(
+            CustomError::msg(format!(
+                "No supported text files are found in the directory. path: {}",
+                directory.display()
+            ))
+            .into(),
+        ))?;
+    }
+
+    let lemmatizer = Lemmatizer::load(ctx).await?;
+    let texts = documents
+        .iter()
+        .map(|(_, segments)| lemmatize_segments(&lemmatizer, segments))
+        .collect::<Vec<_>>();
+
+    let mut df = study_plan(ctx, &texts, known, known_list, target, unlisted_known)?;
+
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path).await
+}
+
+/// exportコマンド
+async fn export_command(ctx: &SessionContext, command: ExportCommands) -> Result<(), Error> {
+    match command {
+        ExportCommands::Anki {
+            pos,
+            filter,
+            sort,
+            unknown,
+            template,
+            front,
+            back,
+            save_template,
+            deck,
+            gloss,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let pos_list = parse_pos_list(pos)?;
+            let sort_keys = sort.map(|sort| parse_sort_keys(&sort)).transpose()?;
+            let card_template = load_template(
+                template.as_deref(),
+                front.as_deref(),
+                back.as_deref(),
+                save_template.as_deref(),
+            )?;
+            let glossary = gloss.map(load_glossary).transpose()?;
+
+            register_data(ctx, SheetType::First).await?;
+            register_data(ctx, SheetType::Third).await?;
+
+            let known_df = if unknown {
+                KnownList::load(&known_path()?)?.register(ctx)?;
+                Some(ctx.table(KNOWN_TABLE_NAME).await?)
+            } else {
+                None
+            };
+
+            let df = simple_query(
+                ctx.table(SheetType::First.table_name().as_str()).await?,
+                SheetType::First,
+                QueryOptions {
+                    pos_list: pos_list.as_deref(),
+                    filter: filter.as_deref(),
+                    known: known_df,
+                    sort_keys: sort_keys.as_deref(),
+                    skip,
+                    limit,
+                    additional_columns: Some(&["PoS".to_string()][..]),
+                    ..Default::default()
+                },
+            )?;
+
+            let forms = load_forms(ctx).await?;
+            let cards = anki_cards(df, &forms, glossary.as_ref()).await?;
+
+            match dist_path {
+                Some(path) => {
+                    let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                    write_anki(&mut writer, &cards, &card_template, deck.as_deref())?;
+                    println!("{} cards are exported to {}.", cards.len(), path.display());
+                }
+                None => {
+                    write_anki(
+                        &mut std::io::stdout().lock(),
+                        &cards,
+                        &card_template,
+                        deck.as_deref(),
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// vstコマンド
+async fn vst_command(ctx: &SessionContext, command: VstCommands) -> Result<(), Error> {
+    match command {
+        VstCommands::Generate {
+            per_band,
+            bands,
+            pos,
+            seed,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            if per_band == 0 {
+                Err(Error::ArgError(
+                    CustomError::msg("The number of items per band must be positive.").into(),
+                ))?;
+            }
+            let bands =
+                BandRange::try_from(bands.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let pos_list = parse_pos_list(pos)?;
+
+            register_data(ctx, SheetType::First).await?;
+
+            let mut df = generate_test(ctx, per_band, bands, pos_list.as_deref(), seed).await?;
+            if skip.is_some() || limit.is_some() {
+                df = df.limit(skip.unwrap_or(0), limit)?;
+            }
+            write_df(df, dist_path.as_deref()).await?;
+        }
+        VstCommands::Score {
+            responses,
+            dist_path,
+        } => {
+            write_sections(score_test(ctx, &responses)?, dist_path.as_deref()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// knownコマンド
+async fn known_command(ctx: &SessionContext, command: KnownCommands) -> Result<(), Error> {
+    let path = known_path()?;
+    let mut known = KnownList::load(&path)?;
+
+    let split_lemmas = |lemmas: &str| {
+        lemmas
+            .split(",")
+            .map(|lemma| lemma.trim().to_owned())
+            .filter(|lemma| !lemma.is_empty())
+            .collect::<Vec<_>>()
+    };
+
+    match command {
+        KnownCommands::Import { file } => {
+            let added = known.extend(&KnownList::read(&file)?);
+            known.save(&path)?;
+            println!("{added} lemmas are imported into {}.", path.display());
+        }
+        KnownCommands::Add { lemmas, pos } => {
+            if let Some(pos) = pos.as_ref() {
+                check_pos_list(&[pos.clone()])?;
+            }
+            let added = split_lemmas(&lemmas)
+                .iter()
+                .filter(|lemma| known.insert(lemma, pos.as_deref()))
+                .count();
+            known.save(&path)?;
+            println!("{added} lemmas are added to {}.", path.display());
+        }
+        KnownCommands::Remove { lemmas, pos } => {
+            let removed = split_lemmas(&lemmas)
+                .iter()
+                .filter(|lemma| known.remove(lemma, pos.as_deref()))
+                .count();
+            known.save(&path)?;
+            println!("{removed} lemmas are removed from {}.", path.display());
+        }
+        KnownCommands::List {
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            known.register(ctx)?;
+            let mut df = ctx
+                .sql(&format!(
+                    r#"SELECT * FROM {KNOWN_TABLE_NAME} ORDER BY lemma, "PoS""#
+                ))
+                .await?;
+            if skip.is_some() || limit.is_some() {
+                df = df.limit(skip.unwrap_or(0), limit)?;
+            }
+            write_df(df, dist_path.as_deref()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// keynessコマンド
+async fn keyness_command(
+    ctx: &SessionContext,
+    target: Genre,
+    reference: Reference,
+    significance: Option<f64>,
+    min_log_ratio: Option<f64>,
+    negative: bool,
+    pos_list: Option<&[String]>,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    let mut df = keyness(
+        ctx,
+        target,
+        reference,
+        significance,
+        min_log_ratio,
+        negative,
+        pos_list,
+    )
+    .await?;
+
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path).await
+}
+
+/// burstyコマンド
+async fn bursty_command(
+    ctx: &SessionContext,
+    top: usize,
+    max_disp: f64,
+    pos_list: Option<&[String]>,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    let mut df = bursty(ctx, top, max_disp, pos_list).await?;
+
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path).await
+}
+
+/// subgenreコマンド
+async fn subgenre_command(ctx: &SessionContext, command: SubgenreCommands) -> Result<(), Error> {
+    let (df, common) = match command {
+        SubgenreCommands::Genres { common } => {
+            // サブジャンルのカラムが無ければ対応のテーブルは登録されていない
+            registered_subgenre_columns(ctx).await?;
+            (ctx.table(SUBGENRE_TABLE_NAME).await?, common)
+        }
+        SubgenreCommands::Rollup { pos, common } => {
+            let pos_list = parse_pos_list(pos)?;
+            (rollup(ctx, pos_list.as_deref()).await?, common)
+        }
+        SubgenreCommands::Compare {
+            genre,
+            lemma,
+            pos,
+            common,
+        } => {
+            let genre = Genre::try_from(genre.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let pos_list = parse_pos_list(pos)?;
+            (
+                compare_within_genre(ctx, genre, lemma.as_deref(), pos_list.as_deref()).await?,
+                common,
+            )
+        }
+        SubgenreCommands::Over {
+            lemma,
+            pos,
+            min_ratio,
+            common,
+        } => {
+            let pos_list = parse_pos_list(pos)?;
+            (
+                over_represented(ctx, &lemma, pos_list.as_deref(), min_ratio).await?,
+                common,
+            )
+        }
+    };
+
+    let Common {
+        dist_path,
+        skip,
+        limit,
+    } = common;
+
+    let mut df = df;
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path.as_deref()).await
+}
+
+/// similarコマンド
+async fn similar_command(
+    ctx: &SessionContext,
+    lemma: &str,
+    target_pos: Option<&str>,
+    source: DistributionSource,
+    distance: Distance,
+    pos_list: Option<&[String]>,
+    bands: Option<BandRange>,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    if let Some(target_pos) = target_pos {
+        check_pos_list(&[target_pos.to_string()])?;
+    }
+
+    let mut df = similar_lemmas(ctx, lemma, target_pos, source, distance, pos_list, bands).await?;
+
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path).await
+}
+
+/// clusterコマンド
+async fn cluster_command(
+    ctx: &SessionContext,
+    source: DistributionSource,
+    k: usize,
+    top: usize,
+    pos_list: Option<&[String]>,
+    max_iterations: usize,
+    seed: u64,
+    output: ClusterOutput,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    dist_path: Option<&Path>,
+) -> Result<(), Error> {
+    let mut df =
+        cluster_lemmas(ctx, source, k, top, pos_list, max_iterations, seed, output).await?;
+
+    if skip.is_some() || limit.is_some() {
+        df = df.limit(skip.unwrap_or(0), limit)?;
+    }
+
+    write_df(df, dist_path).await
+}
+
+/// カンマ区切りの品詞のリストをパースし，値を確認する
+fn parse_pos_list(pos: Option<String>) -> Result<Option<Vec<String>>, Error> {
+    let pos_list = pos.map(|pos_list| {
+        pos_list
+            .split(",")
+            .map(|pos| pos.to_owned())
+            .collect::<Vec<_>>()
+    });
+    if let Some(pos_list) = pos_list.as_deref() {
+        check_pos_list(pos_list)?;
+    }
+    Ok(pos_list)
+}
+
+/// 品詞の値を確認する
+fn check_pos_list(pos_list: &[String]) -> Result<(), Error> {
+    let correct_pos_list = vec![
+        "a".to_string(), // 冠詞
+        "c".to_string(), // 接続詞
+        "d".to_string(), // 限定詞
+        "e".to_string(), // 存在
+        "f".to_string(), // その他
+        "g".to_string(), // ゲルマン所有
+        "i".to_string(), // 前置詞
+        "j".to_string(), // 形容詞
+        "m".to_string(), // 数詞
+        "n".to_string(), // 名詞
+        "p".to_string(), // 代名詞
+        "r".to_string(), // 副詞
+        "t".to_string(), // 不定詞
+        "u".to_string(), // 間投詞
+        "v".to_string(), // 動詞
+        "x".to_string(), // 否定
+        "z".to_string(), // 略称
+    ];
+
+    for pos in pos_list.iter() {
+        if !correct_pos_list.contains(pos) {
+            return Err(Error::ArgError(
+                CustomError::msg(format!(
+                    "Invalid pos value. Choose pos in {correct_pos_list:?}"
+                ))
+                .into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 検索語と`--prefix`・`--suffix`から一致の方法を決める
+fn words_and_match(
+    words: Option<&[String]>,
+    prefix: bool,
+    suffix: bool,
+) -> Result<Option<(&[String], MatchType)>, Error> {
+    match (words, prefix, suffix) {
+        (Some(words), true, false) => Ok(Some((words, MatchType::Prefix))),
+        (Some(words), false, true) => Ok(Some((words, MatchType::Suffix))),
+        (Some(words), false, false) => Ok(Some((words, MatchType::All))),
+        (Some(_), true, true) => Err(Error::ArgError(
+            CustomError::msg("prefix and suffix cannot be specified at the same time.").into(),
+        )),
+        (None, false, false) => Ok(None),
+        (None, _, _) => Err(Error::ArgError(
+            CustomError::msg("You can specify prefix or suffix with search words.").into(),
+        )),
+    }
+}
+
+/// queryコマンド．既知語と訳語のテーブルは`unknown`と`glossary`から用意する．
+async fn query_command(
+    ctx: &SessionContext,
+    sheet_type: SheetType,
+    options: QueryOptions<'_>,
+    unknown: bool,
+    mark_known: bool,
+    glossary: Option<&Glossary>,
     dist_path: Option<&Path>,
 ) -> Result<(), Error> {
-    if let Some(pos_list) = pos_list {
-        let correct_pos_list = vec![
-            "a".to_string(), // 冠詞
-            "c".to_string(), // 接続詞
-            "d".to_string(), // 限定詞
-            "e".to_string(), // 存在
-            "f".to_string(), // その他
-            "g".to_string(), // ゲルマン所有
-            "i".to_string(), // 前置詞
-            "j".to_string(), // 形容詞
-            "m".to_string(), // 数詞
-            "n".to_string(), // 名詞
-            "p".to_string(), // 代名詞
-            "r".to_string(), // 副詞
-            "t".to_string(), // 不定詞
-            "u".to_string(), // 間投詞
-            "v".to_string(), // 動詞
-            "x".to_string(), // 否定
-            "z".to_string(), // 略称
-        ];
-
-        for pos in pos_list.iter() {
-            if !correct_pos_list.contains(pos) {
-                return Err(Error::ArgError(
-                    CustomError::msg(format!(
-                        "Invalid pos value. Choose pos in {correct_pos_list:?}"
-                    ))
-                    .into(),
-                ));
-            }
-        }
+    // 語形のシートにはレンマと品詞が無いので，クエリの前に断る
+    if mark_known && matches!(sheet_type, SheetType::Fourth) {
+        Err(Error::ArgError(
+            CustomError::msg("Invalid sheet type for marking known words.").into(),
+        ))?;
     }
 
-    let words_and_match = match (words, prefix, suffix) {
-        (Some(words), true, false) => Some((words, MatchType::Prefix)),
-        (Some(words), false, true) => Some((words, MatchType::Suffix)),
-        (Some(words), false, false) => Some((words, MatchType::All)),
-        (Some(_), true, true) => {
-            return Err(Error::ArgError(
-                CustomError::msg("prefix and suffix cannot be specified at the same time.").into(),
-            ));
-        }
-        (None, false, false) => None,
-        (None, _, _) => {
-            return Err(Error::ArgError(
-                CustomError::msg("You can specify prefix or suffix with search words.").into(),
-            ));
-        }
-    };
-
     let csv_path = format!("./data/{}", sheet_type.file_name());
 
     if !PathBuf::from(csv_path.clone()).is_file() {
@@ -203,28 +1516,62 @@ The data source files cannot be found. Please download xlsx file from the offici
 
     let df = ctx.read_csv(&csv_path, Default::default()).await?;
 
+    // 既知語のストアは使う場合だけ読み込む
+    let mut known_store = if unknown || mark_known {
+        let path = known_path()?;
+        Some((KnownList::load(&path)?, path))
+    } else {
+        None
+    };
+    let known_df = match known_store.as_ref() {
+        Some((known, _)) if unknown => {
+            known.register(ctx)?;
+            Some(ctx.table(KNOWN_TABLE_NAME).await?)
+        }
+        _ => None,
+    };
+
+    let glosses_df = match glossary {
+        Some(glossary) => {
+            glossary.register(ctx).await?;
+            Some(ctx.table(GLOSS_TABLE_NAME).await?)
+        }
+        None => None,
+    };
+
+    // 既知語として加えるには品詞も必要
+    let mut columns = options.additional_columns.map(|columns| columns.to_vec());
+    if mark_known {
+        columns.get_or_insert_with(Vec::new).push("PoS".to_string());
+    }
+
     let df = simple_query(
         df,
         sheet_type,
-        words_and_match,
-        pos_list,
-        sorted_column,
-        skip,
-        limit,
-        columns,
-        all,
+        QueryOptions {
+            known: known_df,
+            glosses: glosses_df,
+            additional_columns: columns.as_deref(),
+            ..options
+        },
     )?;
 
-    match dist_path {
-        Some(path) => {
-            let opt = DataFrameWriteOptions::new().with_single_file_output(true);
-            df.write_csv(path.to_str().unwrap(), opt, None).await?;
-        }
-        None => {
-            df.show().await?;
-        }
+    if let Some((known, path)) = known_store.as_mut().filter(|_| mark_known) {
+        // クエリを2回実行しないように，一度集めた結果から既知語を加えて出力する
+        let batches = df.clone().collect().await?;
+        let added = known.mark(&batches)?;
+        known.save(path)?;
+        println!("{added} lemmas are marked as known in {}.", path.display());
+
+        let df = if batches.is_empty() {
+            df
+        } else {
+            ctx.read_batches(batches)?
+        };
+        return write_df(df, dist_path).await;
     }
-    Ok(())
+
+    write_df(df, dist_path).await
 }
 
 #[tokio::main]
@@ -237,6 +1584,9 @@ async fn main() -> Result<(), Error> {
         Commands::Sql {
             sql,
             sheets,
+            gloss,
+            known,
+            tables,
             common:
                 Common {
                     dist_path,
@@ -244,25 +1594,28 @@ async fn main() -> Result<(), Error> {
                     limit,
                 },
         } => {
-            let sheets = {
-                sheets
-                    .unwrap_or("1".to_string())
-                    .split(",")
-                    .map(|sheet| sheet.parse())
-                    .collect::<Result<Vec<usize>, _>>()
-                    .map_err(|e| {
-                        Error::ArgError(
-                            CustomError::new("sheets must be integer or list of integer.", e)
-                                .into(),
-                        )
-                    })?
-            };
-
-            for sheet_number in sheets {
-                let sheet_type = TryInto::<SheetType>::try_into(sheet_number)
-                    .map_err(|e| Error::ArgError(e.into()))?;
-
-                register_data(&ctx, sheet_type).await?;
+            let sheet_types = parse_sheets(sheets)?;
+            for sheet_type in sheet_types.iter() {
+                register_data(&ctx, *sheet_type).await?;
+            }
+            // --knownを指定した場合だけ既知語のストアをknownテーブルとして使う．
+            // ストアが無ければ空のテーブルとする
+            if known {
+                KnownList::load(&known_path()?)?.register(&ctx)?;
+            }
+            // --glossを指定した場合だけ辞書を読み込んでglossesテーブルとして使う．
+            // パスが無ければ設定ファイルの辞書を用いる
+            if let Some(path) = gloss {
+                if !sheet_types
+                    .iter()
+                    .any(|sheet_type| matches!(sheet_type, SheetType::First))
+                {
+                    register_data(&ctx, SheetType::First).await?;
+                }
+                load_glossary(path)?.register(&ctx).await?;
+            }
+            for table in external_tables(&tables)? {
+                table.register(&ctx).await?;
             }
 
             sql_command(&ctx, &sql, skip, limit, dist_path.as_deref()).await?;
@@ -272,8 +1625,21 @@ async fn main() -> Result<(), Error> {
             prefix,
             suffix,
             pos,
+            filter,
+            unknown,
+            mark_known,
+            gloss,
             sheet,
             sorted,
+            sort,
+            top,
+            per,
+            profile,
+            save_profile,
+            rank_mode,
+            min_disp,
+            rerank,
+            rerank_by,
             columns,
             all,
             common:
@@ -299,27 +1665,505 @@ async fn main() -> Result<(), Error> {
                     .map(|column| column.to_owned())
                     .collect::<Vec<_>>()
             });
-            let pos_list = pos.map(|pos_list| {
-                pos_list
+            let pos_list = parse_pos_list(pos)?;
+
+            let sort_keys = match (sort, sorted) {
+                (Some(sort), _) => Some(parse_sort_keys(&sort)?),
+                (None, Some(sorted)) => Some(vec![SortKey::desc(sorted)]),
+                (None, None) => None,
+            };
+
+            let grouping = per
+                .map(|per| Grouping::try_from(per.as_str()))
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?;
+            let top_per = top.zip(grouping.as_ref());
+
+            let profile = match profile {
+                Some(profile) => Some(load_profile(&profile, save_profile.as_deref())?),
+                None => None,
+            };
+
+            let rank_mode = rank_mode
+                .map(|rank_mode| RankMode::try_from(rank_mode.as_str()))
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?;
+
+            // ジャンル名は対応するカラム名に変換する
+            let rerank_by = rerank_by.map(|rerank_by| {
+                Genre::try_from(rerank_by.as_str())
+                    .map(|genre| genre.column_name())
+                    .unwrap_or(rerank_by)
+            });
+
+            let glossary = gloss.map(load_glossary).transpose()?;
+
+            register_data(&ctx, sheet_type).await?;
+            // 訳語はlemmasシートのレンマと品詞で引く
+            if glossary.is_some() && !matches!(sheet_type, SheetType::First) {
+                register_data(&ctx, SheetType::First).await?;
+            }
+
+            let options = QueryOptions {
+                words_and_match: words_and_match(words.as_deref(), prefix, suffix)?,
+                pos_list: pos_list.as_deref(),
+                filter: filter.as_deref(),
+                sort_keys: sort_keys.as_deref(),
+                top_per,
+                profile: profile.as_ref(),
+                rank_mode,
+                min_disp,
+                rerank,
+                rerank_by: rerank_by.as_deref(),
+                skip,
+                limit,
+                additional_columns: columns.as_deref(),
+                all,
+                ..Default::default()
+            };
+
+            query_command(
+                &ctx,
+                sheet_type,
+                options,
+                unknown,
+                mark_known,
+                glossary.as_ref(),
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Sample {
+            n,
+            weight,
+            seed,
+            pos,
+            filter,
+            unknown,
+            sheet,
+            columns,
+            all,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let sheet_type = TryInto::<SheetType>::try_into(sheet.unwrap_or(1))
+                .map_err(|e| Error::ArgError(e.into()))?;
+            let weight =
+                SampleWeight::try_from(weight.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let sample = Sample { n, weight, seed };
+
+            let columns = columns.map(|columns| {
+                columns
                     .split(",")
-                    .map(|pos| pos.to_owned())
+                    .map(|column| column.to_owned())
                     .collect::<Vec<_>>()
             });
+            let pos_list = parse_pos_list(pos)?;
 
             register_data(&ctx, sheet_type).await?;
 
+            let options = QueryOptions {
+                pos_list: pos_list.as_deref(),
+                filter: filter.as_deref(),
+                sample: Some(&sample),
+                skip,
+                limit,
+                additional_columns: columns.as_deref(),
+                all,
+                ..Default::default()
+            };
+
             query_command(
                 &ctx,
                 sheet_type,
-                words.as_deref(),
-                prefix,
-                suffix,
+                options,
+                unknown,
+                false,
+                None,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Keyness {
+            target,
+            reference,
+            significance,
+            min_log_ratio,
+            negative,
+            pos,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let target = Genre::try_from(target.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let reference =
+                Reference::try_from(reference.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let pos_list = parse_pos_list(pos)?;
+
+            register_data(&ctx, SheetType::First).await?;
+
+            keyness_command(
+                &ctx,
+                target,
+                reference,
+                significance,
+                min_log_ratio,
+                negative,
                 pos_list.as_deref(),
-                sorted.as_deref(),
                 skip,
                 limit,
-                columns.as_deref(),
-                all,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Known { command } => {
+            known_command(&ctx, command).await?;
+        }
+        Commands::Export { command } => {
+            export_command(&ctx, command).await?;
+        }
+        Commands::Vst { command } => {
+            vst_command(&ctx, command).await?;
+        }
+        Commands::Subgenre { command } => {
+            register_data(&ctx, SheetType::Second).await?;
+
+            subgenre_command(&ctx, command).await?;
+        }
+        Commands::Bursty {
+            top,
+            max_disp,
+            pos,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let pos_list = parse_pos_list(pos)?;
+
+            register_data(&ctx, SheetType::First).await?;
+
+            bursty_command(
+                &ctx,
+                top,
+                max_disp,
+                pos_list.as_deref(),
+                skip,
+                limit,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Extract {
+            genre,
+            reference,
+            min_ratio,
+            min_per_mil,
+            min_coverage,
+            expected_share,
+            no_subgenre,
+            exclude_bands,
+            min_disp,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let target = Genre::try_from(genre.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let references = reference
+                .map(|reference| parse_references(&reference))
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?
+                .unwrap_or_default();
+            let exclude_bands = if exclude_bands.eq_ignore_ascii_case("none") {
+                None
+            } else {
+                Some(
+                    BandRange::try_from(exclude_bands.as_str())
+                        .map_err(|e| Error::ArgError(e.into()))?,
+                )
+            };
+
+            let criteria = ExtractCriteria {
+                references,
+                min_ratio,
+                min_per_mil,
+                min_coverage: (!no_subgenre).then_some(min_coverage),
+                expected_share,
+                exclude_bands,
+                min_disp,
+                ..ExtractCriteria::new(target)
+            };
+
+            register_data(&ctx, SheetType::First).await?;
+            if !no_subgenre {
+                register_data(&ctx, SheetType::Second).await?;
+            }
+
+            let mut df = extract(&ctx, &criteria).await?;
+            if skip.is_some() || limit.is_some() {
+                df = df.limit(skip.unwrap_or(0), limit)?;
+            }
+
+            write_df(df, dist_path.as_deref()).await?;
+        }
+        Commands::Correlation {
+            top,
+            genres,
+            pos,
+            variable,
+            dist_path,
+        } => {
+            let genres = genres
+                .map(|genres| {
+                    genres
+                        .split(",")
+                        .map(Genre::try_from)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?;
+            let pos_list = parse_pos_list(pos)?;
+
+            register_data(&ctx, SheetType::First).await?;
+
+            correlation_command(
+                &ctx,
+                top,
+                genres.as_deref(),
+                pos_list.as_deref(),
+                variable,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Compare {
+            lemma_a,
+            lemma_b,
+            pos,
+            pos_a,
+            pos_b,
+            dist_path,
+        } => {
+            let a = CompareTarget {
+                lemma: lemma_a,
+                pos: pos_a.or(pos.clone()),
+            };
+            let b = CompareTarget {
+                lemma: lemma_b,
+                pos: pos_b.or(pos),
+            };
+
+            register_data(&ctx, SheetType::First).await?;
+            register_data(&ctx, SheetType::Second).await?;
+
+            compare_command(&ctx, &a, &b, dist_path.as_deref()).await?;
+        }
+        Commands::Profile {
+            input,
+            format,
+            known,
+            unlisted,
+            coverage,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let format = format
+                .map(|format| InputFormat::try_from(format.as_str()))
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?;
+
+            register_data(&ctx, SheetType::First).await?;
+            register_data(&ctx, SheetType::Third).await?;
+
+            profile_command(
+                &ctx,
+                &input,
+                format,
+                known,
+                unlisted,
+                coverage,
+                skip,
+                limit,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Coverage {
+            input,
+            format,
+            known,
+            step,
+            max_known,
+            unlisted_known,
+            top_unknown,
+            dist_path,
+        } => {
+            let format = format
+                .map(|format| InputFormat::try_from(format.as_str()))
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?;
+
+            register_data(&ctx, SheetType::First).await?;
+            register_data(&ctx, SheetType::Third).await?;
+
+            coverage_command(
+                &ctx,
+                &input,
+                format,
+                known,
+                step,
+                max_known,
+                unlisted_known,
+                top_unknown,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Plan {
+            directory,
+            target,
+            known,
+            known_list,
+            unlisted_known,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let target = parse_percent(&target).map_err(|e| Error::ArgError(e.into()))?;
+            let known_list = known_list.map(|path| KnownList::read(&path)).transpose()?;
+
+            register_data(&ctx, SheetType::First).await?;
+            register_data(&ctx, SheetType::Third).await?;
+
+            plan_command(
+                &ctx,
+                &directory,
+                target,
+                known,
+                known_list.as_ref(),
+                unlisted_known,
+                skip,
+                limit,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Stats {
+            sheets,
+            group_by,
+            dist_path,
+        } => {
+            let group_by = group_by
+                .map(|group_by| StatsGroup::try_from(group_by.as_str()))
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?;
+
+            let sheet_types = parse_sheets(sheets)?;
+            for sheet_type in sheet_types.iter() {
+                register_data(&ctx, *sheet_type).await?;
+            }
+
+            stats_command(&ctx, &sheet_types, group_by, dist_path.as_deref()).await?;
+        }
+        Commands::Similar {
+            lemma,
+            target_pos,
+            pos,
+            bands,
+            distance,
+            subgenre,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let source = if subgenre {
+                DistributionSource::Subgenre
+            } else {
+                DistributionSource::Genre
+            };
+            let distance =
+                Distance::try_from(distance.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let bands = bands
+                .map(|bands| BandRange::try_from(bands.as_str()))
+                .transpose()
+                .map_err(|e| Error::ArgError(e.into()))?;
+            let pos_list = parse_pos_list(pos)?;
+
+            register_data(&ctx, source.sheet_type()).await?;
+
+            similar_command(
+                &ctx,
+                &lemma,
+                target_pos.as_deref(),
+                source,
+                distance,
+                pos_list.as_deref(),
+                bands,
+                skip,
+                limit,
+                dist_path.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Cluster {
+            k,
+            top,
+            pos,
+            subgenre,
+            output,
+            max_iterations,
+            seed,
+            common:
+                Common {
+                    dist_path,
+                    skip,
+                    limit,
+                },
+        } => {
+            let source = if subgenre {
+                DistributionSource::Subgenre
+            } else {
+                DistributionSource::Genre
+            };
+            let output =
+                ClusterOutput::try_from(output.as_str()).map_err(|e| Error::ArgError(e.into()))?;
+            let pos_list = parse_pos_list(pos)?;
+
+            register_data(&ctx, source.sheet_type()).await?;
+
+            cluster_command(
+                &ctx,
+                source,
+                k,
+                top,
+                pos_list.as_deref(),
+                max_iterations,
+                seed,
+                output,
+                skip,
+                limit,
                 dist_path.as_deref(),
             )
             .await?;
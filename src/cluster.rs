@@ -0,0 +1,296 @@
+use crate::distribution::{DistributionSource, Distributions};
+use crate::random::Random;
+use crate::{CustomError, Error};
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// centroidsに含める中心に近いレンマの数
+const CENTROID_EXAMPLES: usize = 5;
+
+/// clusterコマンドの出力
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClusterOutput {
+    /// レンマごとのクラスタ
+    Assignments,
+    /// クラスタごとの中心
+    Centroids,
+}
+
+impl TryFrom<&str> for ClusterOutput {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "assignments" | "assignment" => Ok(Self::Assignments),
+            "centroids" | "centroid" => Ok(Self::Centroids),
+            _ => Err(CustomError::msg(
+                "Invalid output for clustering. Choose output in [\"assignments\", \"centroids\"]",
+            )),
+        }
+    }
+}
+
+/// k-meansの結果
+struct KMeans {
+    centroids: Vec<Vec<f64>>,
+    assignments: Vec<usize>,
+}
+
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn nearest(centroids: &[Vec<f64>], point: &[f64]) -> (usize, f64) {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(c, centroid)| (c, squared_distance(centroid, point)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+/// k-means++で初期化したk-means
+fn kmeans(points: &[Vec<f64>], k: usize, max_iterations: usize, seed: u64) -> KMeans {
+    let mut random = Random::new(seed);
+
+    let mut centroids = vec![points[random.next_index(points.len())].clone()];
+    while centroids.len() < k {
+        let weights = points
+            .iter()
+            .map(|point| nearest(&centroids, point).1)
+            .collect::<Vec<_>>();
+        centroids.push(points[random.choose_weighted(&weights)].clone());
+    }
+
+    let assign = |centroids: &[Vec<f64>]| {
+        points
+            .iter()
+            .map(|point| nearest(centroids, point).0)
+            .collect::<Vec<_>>()
+    };
+
+    // 反復が0回でも初期の中心への割り当てを返す
+    let mut assignments = assign(&centroids);
+    for _ in 0..max_iterations {
+        // 空のクラスタは前の中心を保つ
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let members = points
+                .iter()
+                .zip(assignments.iter())
+                .filter(|(_, assignment)| **assignment == c)
+                .map(|(point, _)| point)
+                .collect::<Vec<_>>();
+            if members.is_empty() {
+                continue;
+            }
+            for (d, value) in centroid.iter_mut().enumerate() {
+                *value = members.iter().map(|point| point[d]).sum::<f64>() / members.len() as f64;
+            }
+        }
+
+        let next = assign(&centroids);
+        if next == assignments {
+            break;
+        }
+        assignments = next;
+    }
+
+    KMeans {
+        centroids,
+        assignments,
+    }
+}
+
+/// rankの上位`top`語のレンマをジャンル(サブジャンル)の分布でk個のクラスタに分ける．
+///
+/// 分布は`similar`と同じく100万語あたりの頻度を合計1に正規化したベクトルで，
+/// k-means++で初期化したk-meansを用いる．`Assignments`はレンマごとのクラスタと中心までの
+/// 距離を，`Centroids`はクラスタの大きさ，中心の分布(%)と中心に近いレンマを返す．
+pub async fn cluster_lemmas(
+    ctx: &SessionContext,
+    source: DistributionSource,
+    k: usize,
+    top: usize,
+    pos_list: Option<&[String]>,
+    max_iterations: usize,
+    seed: u64,
+    output: ClusterOutput,
+) -> Result<DataFrame, Error> {
+    let distributions = Distributions::load(ctx, source).await?;
+
+    let (indices, points): (Vec<_>, Vec<_>) = distributions
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| pos_list.map_or(true, |pos_list| pos_list.contains(&entry.pos)))
+        .filter_map(|(i, _)| distributions.profile(i).map(|profile| (i, profile)))
+        .take(top)
+        .unzip();
+
+    if k == 0 || points.len() < k {
+        Err(Error::ArgError(
+            CustomError::msg(format!(
+                "The number of clusters must be between 1 and the number of lemmas ({}).",
+                points.len()
+            ))
+            .into(),
+        ))?;
+    }
+
+    let KMeans {
+        centroids,
+        assignments,
+    } = kmeans(&points, k, max_iterations, seed);
+
+    let distances = points
+        .iter()
+        .zip(assignments.iter())
+        .map(|(point, c)| squared_distance(point, &centroids[*c]).sqrt())
+        .collect::<Vec<_>>();
+
+    let batch = match output {
+        ClusterOutput::Assignments => {
+            let entries = indices
+                .iter()
+                .map(|i| &distributions.entries[*i])
+                .collect::<Vec<_>>();
+            RecordBatch::try_from_iter(vec![
+                (
+                    "rank",
+                    Arc::new(Int64Array::from_iter_values(
+                        entries.iter().map(|entry| entry.rank),
+                    )) as ArrayRef,
+                ),
+                (
+                    "lemma",
+                    Arc::new(StringArray::from_iter_values(
+                        entries.iter().map(|entry| entry.lemma.clone()),
+                    )) as ArrayRef,
+                ),
+                (
+                    "PoS",
+                    Arc::new(StringArray::from_iter_values(
+                        entries.iter().map(|entry| entry.pos.clone()),
+                    )) as ArrayRef,
+                ),
+                (
+                    "cluster",
+                    Arc::new(Int64Array::from_iter_values(
+                        assignments.iter().map(|c| *c as i64 + 1),
+                    )) as ArrayRef,
+                ),
+                (
+                    "distance",
+                    Arc::new(Float64Array::from_iter_values(distances.iter().copied())) as ArrayRef,
+                ),
+            ])
+        }
+        ClusterOutput::Centroids => {
+            let examples = (0..k)
+                .map(|c| {
+                    let mut members = assignments
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, assignment)| **assignment == c)
+                        .map(|(i, _)| i)
+                        .collect::<Vec<_>>();
+                    members.sort_by(|a, b| distances[*a].total_cmp(&distances[*b]));
+                    members
+                        .iter()
+                        .take(CENTROID_EXAMPLES)
+                        .map(|i| distributions.entries[indices[*i]].lemma.clone())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .collect::<Vec<_>>();
+
+            let mut columns = vec![
+                (
+                    "cluster".to_string(),
+                    Arc::new(Int64Array::from_iter_values((1..=k).map(|c| c as i64))) as ArrayRef,
+                ),
+                (
+                    "size".to_string(),
+                    Arc::new(Int64Array::from_iter_values((0..k).map(|c| {
+                        assignments
+                            .iter()
+                            .filter(|assignment| **assignment == c)
+                            .count() as i64
+                    }))) as ArrayRef,
+                ),
+            ];
+            for (d, dimension) in distributions.dimensions.iter().enumerate() {
+                columns.push((
+                    dimension.clone(),
+                    Arc::new(Float64Array::from_iter_values(
+                        centroids.iter().map(|centroid| centroid[d] * 100.0),
+                    )) as ArrayRef,
+                ));
+            }
+            columns.push((
+                "examples".to_string(),
+                Arc::new(StringArray::from(examples)) as ArrayRef,
+            ));
+            RecordBatch::try_from_iter(columns)
+        }
+    }
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(ctx.read_batch(batch)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<Vec<f64>> {
+        vec![
+            vec![0.9, 0.1],
+            vec![0.8, 0.2],
+            vec![0.85, 0.15],
+            vec![0.1, 0.9],
+            vec![0.2, 0.8],
+            vec![0.15, 0.85],
+        ]
+    }
+
+    #[test]
+    fn same_seed_gives_same_clusters() {
+        let points = points();
+        for seed in 0..20 {
+            let a = kmeans(&points, 2, 100, seed);
+            let b = kmeans(&points, 2, 100, seed);
+            assert_eq!(a.assignments, b.assignments);
+            assert_eq!(a.centroids, b.centroids);
+        }
+    }
+
+    #[test]
+    fn separates_well_separated_points() {
+        let points = points();
+        for seed in 0..20 {
+            let result = kmeans(&points, 2, 100, seed);
+            let first = result.assignments[0];
+            assert_eq!(result.assignments[..3], [first; 3]);
+            assert_eq!(result.assignments[3..], [1 - first; 3]);
+
+            let centroid = &result.centroids[first];
+            assert!((centroid[0] - 0.85).abs() < 1e-9);
+            assert!((centroid[1] - 0.15).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn zero_iterations_assigns_to_initial_centroids() {
+        let points = points();
+        let result = kmeans(&points, 3, 0, 1);
+        assert_eq!(result.assignments.len(), points.len());
+        assert_eq!(result.centroids.len(), 3);
+        for (point, assignment) in points.iter().zip(result.assignments.iter()) {
+            assert_eq!(nearest(&result.centroids, point).0, *assignment);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use crate::distribution::{DistributionSource, Distributions};
+use crate::{CustomError, Error};
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// 有意水準5%の対数尤度比(自由度1)の臨界値
+const CRITICAL_VALUE: f64 = 3.84;
+
+/// 比較するレンマ
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompareTarget {
+    pub lemma: String,
+    /// 品詞．指定が無ければrankの最も小さい行を用いる．
+    pub pos: Option<String>,
+}
+
+/// 2×2分割表の対数尤度比．a, bは観測頻度，c, dは合計．
+fn log_likelihood(a: f64, b: f64, c: f64, d: f64) -> f64 {
+    let e1 = c * (a + b) / (c + d);
+    let e2 = d * (a + b) / (c + d);
+    let term = |o: f64, e: f64| if o > 0.0 { o * (o / e).ln() } else { 0.0 };
+    2.0 * (term(a, e1) + term(b, e2))
+}
+
+/// 0.5で平滑化したLog Ratio
+fn log_ratio(a: f64, b: f64, c: f64, d: f64) -> f64 {
+    let a = if a > 0.0 { a } else { 0.5 };
+    let b = if b > 0.0 { b } else { 0.5 };
+    ((a / c) / (b / d)).log2()
+}
+
+/// 一方のレンマの分布
+struct Side {
+    rank: i64,
+    pos: String,
+    counts: Vec<f64>,
+    per_mil: Vec<f64>,
+}
+
+fn find_side(distributions: &Distributions, target: &CompareTarget) -> Result<Side, Error> {
+    let index = *distributions
+        .find(&target.lemma, target.pos.as_deref())
+        .first()
+        .ok_or_else(|| {
+            Error::ArgError(
+                CustomError::msg(format!(
+                    "The lemma `{}` is not found in the sheet.",
+                    target.lemma
+                ))
+                .into(),
+            )
+        })?;
+    let entry = &distributions.entries[index];
+
+    Ok(Side {
+        rank: entry.rank,
+        pos: entry.pos.clone(),
+        counts: distributions.counts[index].clone(),
+        per_mil: distributions.per_mil(index),
+    })
+}
+
+/// 全体の検定の統計量
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Statistics {
+    chi_square: f64,
+    /// 対数尤度比(G²)
+    g2: f64,
+    /// 自由度
+    df: usize,
+    cramers_v: f64,
+}
+
+/// どちらかのレンマが分布に無いか頻度を持たなければ，その旨の説明を返す
+fn missing_frequencies(
+    distributions: &Distributions,
+    targets: [&CompareTarget; 2],
+) -> Option<String> {
+    targets.into_iter().find_map(|target| {
+        let total = distributions
+            .find(&target.lemma, target.pos.as_deref())
+            .first()
+            .map(|index| distributions.counts[*index].iter().sum::<f64>());
+        match total {
+            Some(total) if total > 0.0 => None,
+            _ => Some(format!(
+                "The lemma `{}` has no frequencies in the sheet.",
+                target.lemma
+            )),
+        }
+    })
+}
+
+/// 2つのレンマの分布を次元ごとに比較する．
+///
+/// 各次元について2つのレンマの頻度，100万語あたりの頻度，レンマ内での割合(share)を並べ，
+/// 次元とそれ以外の2×2分割表の対数尤度比，Log Ratio，ピアソン残差を求める．
+/// 対数尤度比が5%水準の臨界値以上の次元は`diverges`をtrueとする．
+/// 併せて全体のカイ二乗検定の統計量とCramérのVを返す．
+fn compare_distributions(
+    distributions: &Distributions,
+    a: &CompareTarget,
+    b: &CompareTarget,
+    subgenre: bool,
+) -> Result<(RecordBatch, Statistics), Error> {
+    let side_a = find_side(distributions, a)?;
+    let side_b = find_side(distributions, b)?;
+
+    let total_a = side_a.counts.iter().sum::<f64>();
+    let total_b = side_b.counts.iter().sum::<f64>();
+    if total_a == 0.0 || total_b == 0.0 {
+        Err(Error::ArgError(
+            CustomError::msg("Both lemmas must have frequencies in the sheet.").into(),
+        ))?;
+    }
+    let n = total_a + total_b;
+
+    let mut chi_square = 0.0;
+    let mut g_square = 0.0;
+    let mut log_likelihoods = Vec::new();
+    let mut log_ratios = Vec::new();
+    let mut residuals = Vec::new();
+    for (freq_a, freq_b) in side_a.counts.iter().zip(side_b.counts.iter()) {
+        let dimension_total = freq_a + freq_b;
+        for (observed, row_total) in [(*freq_a, total_a), (*freq_b, total_b)] {
+            let expected = row_total * dimension_total / n;
+            if expected > 0.0 {
+                chi_square += (observed - expected).powi(2) / expected;
+                if observed > 0.0 {
+                    g_square += 2.0 * observed * (observed / expected).ln();
+                }
+            }
+        }
+
+        let expected_a = total_a * dimension_total / n;
+        residuals.push(if expected_a > 0.0 {
+            Some((freq_a - expected_a) / expected_a.sqrt())
+        } else {
+            None
+        });
+        log_likelihoods.push(log_likelihood(*freq_a, *freq_b, total_a, total_b));
+        log_ratios.push(log_ratio(*freq_a, *freq_b, total_a, total_b));
+    }
+
+    // 頻度の無い次元は自由度に数えない
+    let used_dimensions = side_a
+        .counts
+        .iter()
+        .zip(side_b.counts.iter())
+        .filter(|(freq_a, freq_b)| **freq_a + **freq_b > 0.0)
+        .count();
+    let degrees_of_freedom = used_dimensions.saturating_sub(1);
+    let cramers_v = if used_dimensions > 1 {
+        (chi_square / n).sqrt()
+    } else {
+        0.0
+    };
+
+    let mut columns = Vec::new();
+    if subgenre {
+        columns.push((
+            "genre".to_string(),
+            Arc::new(StringArray::from_iter_values(
+                distributions.genres.iter().map(|genre| genre.column_name()),
+            )) as ArrayRef,
+        ));
+    }
+    columns.push((
+        if subgenre { "subgenre" } else { "genre" }.to_string(),
+        Arc::new(StringArray::from(distributions.dimensions.clone())) as ArrayRef,
+    ));
+    for (label, side, total) in [("a", &side_a, total_a), ("b", &side_b, total_b)] {
+        columns.push((
+            format!("freq_{label}"),
+            Arc::new(Float64Array::from(side.counts.clone())) as ArrayRef,
+        ));
+        columns.push((
+            format!("per_mil_{label}"),
+            Arc::new(Float64Array::from(side.per_mil.clone())) as ArrayRef,
+        ));
+        columns.push((
+            format!("share_{label}"),
+            Arc::new(Float64Array::from_iter_values(
+                side.counts.iter().map(|count| count * 100.0 / total),
+            )) as ArrayRef,
+        ));
+    }
+    columns.push((
+        "log_likelihood".to_string(),
+        Arc::new(Float64Array::from(log_likelihoods.clone())) as ArrayRef,
+    ));
+    columns.push((
+        "log_ratio".to_string(),
+        Arc::new(Float64Array::from(log_ratios)) as ArrayRef,
+    ));
+    columns.push((
+        "residual".to_string(),
+        Arc::new(Float64Array::from(residuals)) as ArrayRef,
+    ));
+    columns.push((
+        "diverges".to_string(),
+        Arc::new(BooleanArray::from_iter(
+            log_likelihoods
+                .iter()
+                .map(|log_likelihood| Some(*log_likelihood >= CRITICAL_VALUE)),
+        )) as ArrayRef,
+    ));
+
+    let batch = RecordBatch::try_from_iter(columns).map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok((
+        batch,
+        Statistics {
+            chi_square,
+            g2: g_square,
+            df: degrees_of_freedom,
+            cramers_v,
+        },
+    ))
+}
+
+/// 2つのレンマのジャンル・サブジャンルの分布を比較する．
+///
+/// 結果はセクション名とデータフレームの組のリストで，全体の検定(summary)，
+/// ジャンルごとの比較(genres)，サブジャンルごとの比較(subgenres)を返す．
+/// レンマの頻度の合計にはそれぞれのシートのジャンル(サブジャンル)のカラムの合計を用いる．
+/// どちらかのレンマがsubgenresシートで頻度を持たなければ，サブジャンルの比較は省き，
+/// summaryの`note`にその理由を残す．
+pub async fn compare_lemmas(
+    ctx: &SessionContext,
+    a: &CompareTarget,
+    b: &CompareTarget,
+) -> Result<Vec<(String, DataFrame)>, Error> {
+    let mut sections = Vec::new();
+
+    // 品詞はlemmasシートで決め，subgenresシートでも同じ品詞の行を用いる
+    let genres = Distributions::load(ctx, DistributionSource::Genre).await?;
+    let side_a = find_side(&genres, a)?;
+    let side_b = find_side(&genres, b)?;
+    let labels = [(a, &side_a), (b, &side_b)]
+        .map(|(target, side)| format!("{} ({}, rank {})", target.lemma, side.pos, side.rank));
+    let [a, b] = [(a, &side_a), (b, &side_b)].map(|(target, side)| CompareTarget {
+        lemma: target.lemma.clone(),
+        pos: Some(side.pos.clone()),
+    });
+
+    sections.push((
+        "genres".to_string(),
+        Ok(compare_distributions(&genres, &a, &b, false)?),
+    ));
+
+    let subgenres = Distributions::load(ctx, DistributionSource::Subgenre).await?;
+    let compared = match missing_frequencies(&subgenres, [&a, &b]) {
+        Some(note) => Err(note),
+        None => Ok(compare_distributions(&subgenres, &a, &b, true)?),
+    };
+    sections.push(("subgenres".to_string(), compared));
+
+    let statistics_of = |section: &(String, Result<(RecordBatch, Statistics), String>)| {
+        section.1.as_ref().ok().map(|(_, statistics)| *statistics)
+    };
+
+    let summary_batch = RecordBatch::try_from_iter(vec![
+        (
+            "level",
+            Arc::new(StringArray::from_iter_values(
+                sections.iter().map(|(section, _)| section.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma_a",
+            Arc::new(StringArray::from_iter_values(
+                sections.iter().map(|_| labels[0].clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma_b",
+            Arc::new(StringArray::from_iter_values(
+                sections.iter().map(|_| labels[1].clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "chi_square",
+            Arc::new(Float64Array::from_iter(sections.iter().map(|section| {
+                statistics_of(section).map(|statistics| statistics.chi_square)
+            }))) as ArrayRef,
+        ),
+        (
+            "log_likelihood",
+            Arc::new(Float64Array::from_iter(sections.iter().map(|section| {
+                statistics_of(section).map(|statistics| statistics.g2)
+            }))) as ArrayRef,
+        ),
+        (
+            "df",
+            Arc::new(Int64Array::from_iter(sections.iter().map(|section| {
+                statistics_of(section).map(|statistics| statistics.df as i64)
+            }))) as ArrayRef,
+        ),
+        (
+            "cramers_v",
+            Arc::new(Float64Array::from_iter(sections.iter().map(|section| {
+                statistics_of(section).map(|statistics| statistics.cramers_v)
+            }))) as ArrayRef,
+        ),
+        (
+            "note",
+            Arc::new(StringArray::from_iter(
+                sections
+                    .iter()
+                    .map(|(_, compared)| compared.as_ref().err().cloned()),
+            )) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    let mut result = vec![("summary".to_string(), ctx.read_batch(summary_batch)?)];
+    for (section, compared) in sections {
+        if let Ok((batch, _)) = compared {
+            result.push((section, ctx.read_batch(batch)?));
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,63 @@
+use crate::export::CardTemplate;
+use crate::{CustomError, Error};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// 設定ファイルのデフォルトのパス
+pub const DEFAULT_CONFIG_PATH: &str = "./coca.toml";
+
+/// 設定ファイル(toml)の内容
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// 名前付きのレジスタープロファイル．ジャンル名から重みへの対応．
+    #[serde(default)]
+    pub profiles: BTreeMap<String, BTreeMap<String, f64>>,
+
+    /// 既知語のストアのパス．指定が無ければ`./known.csv`とする．
+    #[serde(default)]
+    pub known_words: Option<String>,
+
+    /// 訳語を引く辞書ファイルのパス
+    #[serde(default)]
+    pub glossary: Option<String>,
+
+    /// sqlで使う外部のファイル．テーブル名からパスへの対応．
+    #[serde(default)]
+    pub tables: BTreeMap<String, String>,
+
+    /// 名前付きのAnkiのカードテンプレート
+    #[serde(default)]
+    pub templates: BTreeMap<String, CardTemplate>,
+}
+
+impl Config {
+    /// 設定ファイルを読み込む．ファイルが無い場合はデフォルトの設定とする．
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| {
+            Error::ConfigError(
+                CustomError::new(
+                    format!("Failed to parse the config file. path: {}", path.display()),
+                    e,
+                )
+                .into(),
+            )
+        })
+    }
+    /// 設定ファイルに書き込む
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = toml::to_string_pretty(self).map_err(|e| {
+            Error::ConfigError(CustomError::new("Failed to serialize the config.", e).into())
+        })?;
+        std::fs::write(path, content)?;
+
+        Ok(())
+    }
+}
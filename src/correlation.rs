@@ -0,0 +1,256 @@
+use crate::distribution::{DistributionSource, Distributions};
+use crate::{CustomError, Error, Genre};
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// 同順位を平均順位とした順位(1始まり)．値の降順に順位を付ける．
+fn average_ranks(values: &[f64]) -> Vec<f64> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| values[*b].total_cmp(&values[*a]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end + 1) as f64 / 2.0;
+        for i in order[start..end].iter() {
+            ranks[*i] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// 順位のピアソン相関(Spearmanの順位相関係数)．
+/// どちらかの順位がすべて同じで分散が0なら定義できないのでNoneを返す．
+fn spearman(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len() as f64;
+    let mean_a = a.iter().sum::<f64>() / n;
+    let mean_b = b.iter().sum::<f64>() / n;
+    let covariance = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| (x - mean_a) * (y - mean_b))
+        .sum::<f64>();
+    let variance_a = a.iter().map(|x| (x - mean_a).powi(2)).sum::<f64>();
+    let variance_b = b.iter().map(|y| (y - mean_b).powi(2)).sum::<f64>();
+    let denominator = (variance_a * variance_b).sqrt();
+    (denominator > 0.0).then(|| covariance / denominator)
+}
+
+/// 同順位を補正したKendallのτ_b．どちらかがすべて同順位ならNoneを返す．
+fn kendall(a: &[f64], b: &[f64]) -> Option<f64> {
+    let (mut concordant, mut discordant, mut ties_a, mut ties_b) = (0_i64, 0_i64, 0_i64, 0_i64);
+    for i in 0..a.len() {
+        for j in (i + 1)..a.len() {
+            let sign_a = a[i].total_cmp(&a[j]) as i64;
+            let sign_b = b[i].total_cmp(&b[j]) as i64;
+            match (sign_a, sign_b) {
+                (0, 0) => {}
+                (0, _) => ties_a += 1,
+                (_, 0) => ties_b += 1,
+                _ if sign_a == sign_b => concordant += 1,
+                _ => discordant += 1,
+            }
+        }
+    }
+    let n_a = (concordant + discordant + ties_a) as f64;
+    let n_b = (concordant + discordant + ties_b) as f64;
+    let denominator = (n_a * n_b).sqrt();
+    (denominator > 0.0).then(|| (concordant - discordant) as f64 / denominator)
+}
+
+/// ジャンル間の順位相関と，ジャンルによって順位の変わるレンマを求める．
+///
+/// rankの上位`top`語について各ジャンルの頻度で順位を付け直し，ジャンルの組ごとの
+/// Spearman・Kendallの順位相関(correlations)と，ジャンル別順位の標準偏差(rank_sd)の
+/// 大きい順に`variable`語のレンマ(variability)を返す．対象のレンマがすべて同じ頻度の
+/// ジャンルを含む組では，順位相関はnullになる．
+pub async fn genre_correlations(
+    ctx: &SessionContext,
+    top: usize,
+    genres: Option<&[Genre]>,
+    pos_list: Option<&[String]>,
+    variable: usize,
+) -> Result<Vec<(String, DataFrame)>, Error> {
+    let distributions = Distributions::load(ctx, DistributionSource::Genre).await?;
+
+    let dimensions = distributions
+        .genres
+        .iter()
+        .enumerate()
+        .filter(|(_, genre)| genres.map_or(true, |genres| genres.contains(genre)))
+        .map(|(d, genre)| (d, *genre))
+        .collect::<Vec<_>>();
+    if dimensions.len() < 2 {
+        Err(Error::ArgError(
+            CustomError::msg("At least two genres are required for rank correlations.").into(),
+        ))?;
+    }
+
+    let indices = distributions
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| pos_list.map_or(true, |pos_list| pos_list.contains(&entry.pos)))
+        .map(|(i, _)| i)
+        .take(top)
+        .collect::<Vec<_>>();
+    if indices.len() < 2 {
+        Err(Error::ArgError(
+            CustomError::msg("At least two lemmas are required for rank correlations.").into(),
+        ))?;
+    }
+
+    // ジャンルごとの順位
+    let genre_ranks = dimensions
+        .iter()
+        .map(|(d, _)| {
+            average_ranks(
+                &indices
+                    .iter()
+                    .map(|i| distributions.counts[*i][*d])
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // correlations
+    let mut genre_a = Vec::new();
+    let mut genre_b = Vec::new();
+    let mut spearmans = Vec::new();
+    let mut kendalls = Vec::new();
+    for a in 0..dimensions.len() {
+        for b in (a + 1)..dimensions.len() {
+            genre_a.push(dimensions[a].1.column_name());
+            genre_b.push(dimensions[b].1.column_name());
+            spearmans.push(spearman(&genre_ranks[a], &genre_ranks[b]));
+            kendalls.push(kendall(&genre_ranks[a], &genre_ranks[b]));
+        }
+    }
+    let correlations = RecordBatch::try_from_iter(vec![
+        ("genre_a", Arc::new(StringArray::from(genre_a)) as ArrayRef),
+        ("genre_b", Arc::new(StringArray::from(genre_b)) as ArrayRef),
+        (
+            "lemmas",
+            Arc::new(Int64Array::from(vec![
+                indices.len() as i64;
+                spearmans.len()
+            ])) as ArrayRef,
+        ),
+        (
+            "spearman",
+            Arc::new(Float64Array::from(spearmans)) as ArrayRef,
+        ),
+        (
+            "kendall",
+            Arc::new(Float64Array::from(kendalls)) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    // variability
+    let mut stability = (0..indices.len())
+        .map(|n| {
+            let ranks = genre_ranks.iter().map(|ranks| ranks[n]).collect::<Vec<_>>();
+            let mean = ranks.iter().sum::<f64>() / ranks.len() as f64;
+            let sd = (ranks.iter().map(|rank| (rank - mean).powi(2)).sum::<f64>()
+                / ranks.len() as f64)
+                .sqrt();
+            (n, sd)
+        })
+        .collect::<Vec<_>>();
+    stability.sort_by(|a, b| b.1.total_cmp(&a.1));
+    stability.truncate(variable);
+
+    let entries = stability
+        .iter()
+        .map(|(n, _)| &distributions.entries[indices[*n]])
+        .collect::<Vec<_>>();
+    let mut columns = vec![
+        (
+            "rank".to_string(),
+            Arc::new(Int64Array::from_iter_values(
+                entries.iter().map(|entry| entry.rank),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma".to_string(),
+            Arc::new(StringArray::from_iter_values(
+                entries.iter().map(|entry| entry.lemma.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "PoS".to_string(),
+            Arc::new(StringArray::from_iter_values(
+                entries.iter().map(|entry| entry.pos.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "rank_sd".to_string(),
+            Arc::new(Float64Array::from_iter_values(
+                stability.iter().map(|(_, sd)| *sd),
+            )) as ArrayRef,
+        ),
+    ];
+    for ((_, genre), ranks) in dimensions.iter().zip(genre_ranks.iter()) {
+        columns.push((
+            format!("{}_rank", genre.column_name()),
+            Arc::new(Float64Array::from_iter_values(
+                stability.iter().map(|(n, _)| ranks[*n]),
+            )) as ArrayRef,
+        ));
+    }
+    let variability =
+        RecordBatch::try_from_iter(columns).map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(vec![
+        ("correlations".to_string(), ctx.read_batch(correlations)?),
+        ("variability".to_string(), ctx.read_batch(variability)?),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_ranks_descending_with_ties() {
+        assert_eq!(
+            average_ranks(&[10.0, 30.0, 20.0, 20.0]),
+            vec![4.0, 1.0, 2.5, 2.5]
+        );
+    }
+
+    #[test]
+    fn spearman_of_ranks() {
+        // 1 - 6 * 2 / (4 * (16 - 1)) = 0.8
+        let value = spearman(&[1.0, 2.0, 3.0, 4.0], &[1.0, 3.0, 2.0, 4.0]).unwrap();
+        assert!((value - 0.8).abs() < 1e-12);
+        assert_eq!(spearman(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]), Some(-1.0));
+    }
+
+    #[test]
+    fn kendall_tau_b_with_ties() {
+        // 一致3組，不一致1組，aのみ同順位1組，bのみ同順位1組
+        // τ_b = (3 - 1) / sqrt((3 + 1 + 1) * (3 + 1 + 1)) = 0.4
+        let value = kendall(&[1.0, 2.0, 2.0, 3.0], &[1.0, 3.0, 2.0, 2.0]).unwrap();
+        assert!((value - 0.4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn zero_variance_gives_none() {
+        let constant = average_ranks(&[0.0, 0.0, 0.0]);
+        let ranks = average_ranks(&[3.0, 1.0, 2.0]);
+        assert_eq!(spearman(&constant, &ranks), None);
+        assert_eq!(kendall(&constant, &ranks), None);
+        assert_eq!(kendall(&ranks, &constant), None);
+    }
+}
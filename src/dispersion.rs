@@ -0,0 +1,166 @@
+use crate::query::sum_over_all_expr;
+use crate::subgenre::quote_literal;
+use crate::{CustomError, Error, Genre, SheetType};
+
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::DFSchema;
+use datafusion::logical_expr;
+use datafusion::prelude::{DataFrame, Expr, SessionContext};
+
+/// 分散度(Juilland's D)のカラム名
+pub const DISP_COLUMN: &str = "disp";
+
+/// 頻度と分散度を組み合わせた順位付けの方法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RankMode {
+    /// 頻度のみ
+    Freq,
+    /// 頻度と分散度の積(Juilland's U)
+    Usage,
+    /// ジャンルを部分コーパスとしたRosengrenの調整頻度
+    Adjusted,
+}
+
+impl RankMode {
+    /// 順位付けに用いるスコアのカラム名
+    pub fn column_name(&self) -> String {
+        match self {
+            Self::Freq => "freq",
+            Self::Usage => "usage",
+            Self::Adjusted => "adjusted_freq",
+        }
+        .to_string()
+    }
+
+    /// スコアの式．`Freq`の場合はシートのカラムをそのまま用いるのでNone．
+    ///
+    /// `Adjusted`はジャンルiの大きさの割合をs_i，頻度をf_iとして(Σ√(s_i f_i))²を求める．
+    /// 頻度がジャンルの大きさに比例して均等に現れるほど元の頻度に近づく．
+    pub fn score_expr(&self, schema: &DFSchema) -> Result<Option<Expr>, Error> {
+        let has_column = |column: &str| schema.has_column_with_unqualified_name(column);
+        let as_float = |column: &str| {
+            let column = logical_expr::col(format!(r#""{column}""#));
+            logical_expr::when(column.clone().is_null(), logical_expr::lit(0.0))
+                .otherwise(logical_expr::cast(column, DataType::Float64))
+                .unwrap()
+        };
+
+        match self {
+            Self::Freq => {
+                if !has_column("freq") {
+                    Err(Error::ArgError(
+                        CustomError::msg("The specified sheet has no freq column.").into(),
+                    ))?;
+                }
+                Ok(None)
+            }
+            Self::Usage => {
+                if !has_column("freq") || !has_column(DISP_COLUMN) {
+                    Err(Error::ArgError(
+                        CustomError::msg("The specified sheet has no freq and disp columns.")
+                            .into(),
+                    ))?;
+                }
+                Ok(Some(as_float("freq") * as_float(DISP_COLUMN)))
+            }
+            Self::Adjusted => {
+                let genres = Genre::all()
+                    .into_iter()
+                    .filter(|genre| has_column(&genre.column_name()))
+                    .collect::<Vec<_>>();
+                if genres.is_empty() {
+                    Err(Error::ArgError(
+                        CustomError::msg("The specified sheet has no genre columns.").into(),
+                    ))?;
+                }
+
+                let all_total = genres
+                    .iter()
+                    .map(|genre| sum_over_all_expr(as_float(&genre.column_name())))
+                    .reduce(|acc, expr| acc + expr)
+                    .unwrap();
+                let sum_of_roots = genres
+                    .iter()
+                    .map(|genre| {
+                        let freq = as_float(&genre.column_name());
+                        let share = sum_over_all_expr(freq.clone()) / all_total.clone();
+                        logical_expr::sqrt(share * freq)
+                    })
+                    .reduce(|acc, expr| acc + expr)
+                    .unwrap();
+
+                Ok(Some(sum_of_roots.clone() * sum_of_roots))
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for RankMode {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "freq" => Ok(Self::Freq),
+            "usage" | "freq*disp" | "freqxdisp" => Ok(Self::Usage),
+            "adjusted" | "adjusted_freq" => Ok(Self::Adjusted),
+            _ => Err(CustomError::msg(
+                "Invalid rank mode. Choose mode in [\"freq\", \"usage\", \"adjusted\"]",
+            )),
+        }
+    }
+}
+
+/// 頻度に比べて分散度の低い(少数のテキストに偏って現れる)レンマを返す．
+///
+/// rankの上位`top`語のうちdispが`max_disp`未満のものについて，頻度と分散度の積(usage)
+/// による順位(usage_rank)と元のrankの差(rank_drop)を求め，その降順で返す．
+pub async fn bursty(
+    ctx: &SessionContext,
+    top: usize,
+    max_disp: f64,
+    pos_list: Option<&[String]>,
+) -> Result<DataFrame, Error> {
+    let table = SheetType::First.table_name();
+    let schema = ctx.table(table.as_str()).await?.schema().clone();
+    if !schema.has_column_with_unqualified_name(DISP_COLUMN) {
+        Err(Error::DataFrameError(
+            CustomError::msg("The lemmas sheet has no disp column.").into(),
+        ))?;
+    }
+
+    // rangeはテキスト数なので，あれば併せて出力する
+    let range = if schema.has_column_with_unqualified_name("range") {
+        r#", "range""#
+    } else {
+        ""
+    };
+    let mut conditions = vec![format!(r#""rank" <= {top}"#), format!("disp < {max_disp}")];
+    if let Some(pos_list) = pos_list {
+        conditions.push(format!(
+            r#""PoS" IN ({})"#,
+            pos_list
+                .iter()
+                .map(|pos| quote_literal(pos))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    let sql = format!(
+        r#"WITH scored AS (
+            SELECT "rank", lemma, "PoS", CAST(freq AS DOUBLE) AS freq,
+                CAST(disp AS DOUBLE) AS disp{range},
+                CAST(freq AS DOUBLE) * CAST(disp AS DOUBLE) AS usage
+            FROM {table}
+        ), ranked AS (
+            SELECT *, ROW_NUMBER() OVER (ORDER BY usage DESC, "rank" ASC) AS usage_rank
+            FROM scored
+        )
+        SELECT *, CAST(usage_rank AS BIGINT) - CAST("rank" AS BIGINT) AS rank_drop
+        FROM ranked
+        WHERE {conditions}
+        ORDER BY rank_drop DESC, "rank" ASC"#,
+        conditions = conditions.join(" AND "),
+    );
+
+    Ok(ctx.sql(&sql).await?)
+}
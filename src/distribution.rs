@@ -0,0 +1,223 @@
+use crate::batch::{f64_column, string_column};
+use crate::subgenre::registered_subgenre_columns;
+use crate::{CustomError, Error, Genre, SheetType};
+
+use datafusion::prelude::SessionContext;
+
+/// 分布を求めるカラムの種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistributionSource {
+    /// lemmasシートのジャンルのカラム
+    Genre,
+    /// subgenresシートのサブジャンルのカラム
+    Subgenre,
+}
+
+impl DistributionSource {
+    pub fn sheet_type(&self) -> SheetType {
+        match self {
+            Self::Genre => SheetType::First,
+            Self::Subgenre => SheetType::Second,
+        }
+    }
+}
+
+impl TryFrom<&str> for DistributionSource {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "genre" | "genres" => Ok(Self::Genre),
+            "subgenre" | "subgenres" => Ok(Self::Subgenre),
+            _ => Err(CustomError::msg(
+                "Invalid distribution source. Choose source in [\"genre\", \"subgenre\"]",
+            )),
+        }
+    }
+}
+
+/// レンマのキー
+#[derive(Debug, Clone, PartialEq)]
+pub struct LemmaEntry {
+    pub rank: i64,
+    pub lemma: String,
+    pub pos: String,
+}
+
+/// レンマごとのジャンル(サブジャンル)別の頻度
+#[derive(Debug, Clone)]
+pub struct Distributions {
+    /// 次元(ジャンルまたはサブジャンルのカラム名)
+    pub dimensions: Vec<String>,
+    /// 次元ごとのサブジャンルの上位のジャンル
+    pub genres: Vec<Genre>,
+    pub entries: Vec<LemmaEntry>,
+    /// 各レンマの次元ごとの頻度
+    pub counts: Vec<Vec<f64>>,
+    /// 次元ごとの頻度の合計(コーパスの大きさの代わりに用いる)
+    pub totals: Vec<f64>,
+}
+
+impl Distributions {
+    /// 登録済みのシートから読み込む．rankの昇順に並べる．
+    pub async fn load(ctx: &SessionContext, source: DistributionSource) -> Result<Self, Error> {
+        let table = source.sheet_type().table_name();
+
+        let (dimensions, genres) = match source {
+            DistributionSource::Genre => {
+                let schema = ctx.table(table.as_str()).await?.schema().clone();
+                Genre::all()
+                    .into_iter()
+                    .filter(|genre| schema.has_column_with_unqualified_name(&genre.column_name()))
+                    .map(|genre| (genre.column_name(), genre))
+                    .unzip::<_, _, Vec<_>, Vec<_>>()
+            }
+            DistributionSource::Subgenre => registered_subgenre_columns(ctx)
+                .await?
+                .into_iter()
+                .map(|column| (column.column, column.genre))
+                .unzip::<_, _, Vec<_>, Vec<_>>(),
+        };
+
+        if dimensions.is_empty() {
+            Err(Error::DataFrameError(
+                CustomError::msg("No genre columns are found in the sheet.").into(),
+            ))?;
+        }
+
+        let sql = format!(
+            r#"SELECT "rank", lemma, "PoS", {} FROM {table} ORDER BY "rank""#,
+            dimensions
+                .iter()
+                .map(|dimension| format!(r#""{}""#, dimension.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let batches = ctx.sql(&sql).await?.collect().await?;
+
+        let ranks = f64_column(&batches, "rank")?;
+        let lemmas = string_column(&batches, "lemma")?;
+        let pos_list = string_column(&batches, "PoS")?;
+        let dimension_values = dimensions
+            .iter()
+            .map(|dimension| f64_column(&batches, dimension))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut entries = Vec::new();
+        let mut counts = Vec::new();
+        for (i, ((rank, lemma), pos)) in ranks.into_iter().zip(lemmas).zip(pos_list).enumerate() {
+            let (Some(rank), Some(lemma)) = (rank, lemma) else {
+                continue;
+            };
+            entries.push(LemmaEntry {
+                rank: rank.round() as i64,
+                lemma,
+                pos: pos.unwrap_or_default(),
+            });
+            counts.push(
+                dimension_values
+                    .iter()
+                    .map(|values| values[i].unwrap_or(0.0).max(0.0))
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        let totals = (0..dimensions.len())
+            .map(|d| counts.iter().map(|count| count[d]).sum::<f64>())
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            dimensions,
+            genres,
+            entries,
+            counts,
+            totals,
+        })
+    }
+
+    /// lemma(とPoS)に一致する行の位置
+    pub fn find(&self, lemma: &str, pos: Option<&str>) -> Vec<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.lemma == lemma && pos.map_or(true, |pos| entry.pos == pos))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// 100万語あたりの頻度
+    pub fn per_mil(&self, index: usize) -> Vec<f64> {
+        self.counts[index]
+            .iter()
+            .zip(self.totals.iter())
+            .map(|(count, total)| {
+                if *total > 0.0 {
+                    count * 1_000_000.0 / total
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// コーパスの大きさで正規化した頻度を合計1にした分布．頻度が全て0ならNone．
+    pub fn profile(&self, index: usize) -> Option<Vec<f64>> {
+        let per_mil = self.per_mil(index);
+        let sum = per_mil.iter().sum::<f64>();
+        if sum > 0.0 {
+            Some(per_mil.into_iter().map(|value| value / sum).collect())
+        } else {
+            None
+        }
+    }
+}
+
+/// 分布の距離
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distance {
+    /// 1 - コサイン類似度
+    Cosine,
+    /// Jensen-Shannonダイバージェンス(底2)の平方根
+    JensenShannon,
+}
+
+impl TryFrom<&str> for Distance {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "cosine" | "cos" => Ok(Self::Cosine),
+            "js" | "jensen-shannon" | "jensen_shannon" => Ok(Self::JensenShannon),
+            _ => Err(CustomError::msg(
+                "Invalid distance. Choose distance in [\"cosine\", \"js\"]",
+            )),
+        }
+    }
+}
+
+impl Distance {
+    pub fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        match self {
+            Self::Cosine => {
+                let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+                let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+                let norm_b = b.iter().map(|y| y * y).sum::<f64>().sqrt();
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    1.0
+                } else {
+                    (1.0 - dot / (norm_a * norm_b)).max(0.0)
+                }
+            }
+            Self::JensenShannon => {
+                let kl = |p: f64, m: f64| if p > 0.0 { p * (p / m).log2() } else { 0.0 };
+                let divergence = a
+                    .iter()
+                    .zip(b)
+                    .map(|(p, q)| {
+                        let m = (p + q) / 2.0;
+                        (kl(*p, m) + kl(*q, m)) / 2.0
+                    })
+                    .sum::<f64>();
+                divergence.max(0.0).sqrt()
+            }
+        }
+    }
+}
@@ -0,0 +1,738 @@
+use crate::{CustomError, Error};
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// 入力ファイルの形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    /// プレーンテキスト
+    Text,
+    /// SubRip(.srt)
+    Srt,
+    /// WebVTT(.vtt)
+    Vtt,
+    /// Advanced SubStation Alpha(.ass, .ssa)
+    Ass,
+    /// HTML(.html, .htm, .xhtml)
+    Html,
+    /// EPUB(.epub)
+    Epub,
+}
+
+impl InputFormat {
+    /// 拡張子から形式を推定する．不明な拡張子はプレーンテキストとする．
+    pub fn from_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|extension| Self::try_from(extension.to_string_lossy().as_ref()).ok())
+            .unwrap_or(Self::Text)
+    }
+}
+
+impl TryFrom<&str> for InputFormat {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "txt" | "text" => Ok(Self::Text),
+            "srt" => Ok(Self::Srt),
+            "vtt" | "webvtt" => Ok(Self::Vtt),
+            "ass" | "ssa" => Ok(Self::Ass),
+            "html" | "htm" | "xhtml" => Ok(Self::Html),
+            "epub" => Ok(Self::Epub),
+            _ => Err(CustomError::msg(
+                "Invalid input format. Choose format in [\"txt\", \"srt\", \"vtt\", \"ass\", \"html\", \"epub\"]",
+            )),
+        }
+    }
+}
+
+/// 文書の一部分．字幕では1つのキュー，テキストでは1行に当たる．
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// 章などの区切りの名前
+    pub section: Option<String>,
+    /// 字幕の開始時刻などの位置
+    pub position: Option<String>,
+    pub text: String,
+}
+
+/// `<i>`のようなタグと`{\an8}`のような上書きコードを取り除き，空白を詰める．
+/// `a < b`のようにタグ名が続かない`<`や，閉じられていない括弧は本文として残す．
+pub fn strip_markup(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let end = match c {
+            // `</i>`やWebVTTの`<00:00:01.000>`もタグとして扱う
+            '<' if rest[1..].starts_with(|c: char| c.is_alphanumeric() || c == '/') => {
+                rest.find('>')
+            }
+            '{' => rest.find('}'),
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                stripped.push(' ');
+                rest = &rest[end + 1..];
+            }
+            None => {
+                stripped.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `00:01:02,345`や`0:01:02.34`の時刻を`00:01:02.345`の形に揃える
+fn normalize_timestamp(timestamp: &str) -> String {
+    let timestamp = timestamp.trim().replace(',', ".");
+    let (time, fraction) = timestamp.split_once('.').unwrap_or((&timestamp, "0"));
+    let mut parts = time
+        .split(':')
+        .map(|part| part.parse::<u32>().unwrap_or(0))
+        .collect::<Vec<_>>();
+    while parts.len() < 3 {
+        parts.insert(0, 0);
+    }
+    // 小数部は先頭の数字だけを使い，3桁に満たなければ0で埋める
+    let millis = fraction
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .chain(std::iter::repeat('0'))
+        .take(3)
+        .collect::<String>();
+    format!("{:02}:{:02}:{:02}.{millis}", parts[0], parts[1], parts[2])
+}
+
+/// SubRipとWebVTTのキューをパースする．空行で区切られたブロックのうち`-->`を含む行を
+/// タイミングとし，それ以降の行をテキストとする．
+fn parse_cues(source: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut position: Option<String> = None;
+    let mut lines: Vec<String> = Vec::new();
+
+    let mut flush = |position: &mut Option<String>, lines: &mut Vec<String>| {
+        if let Some(position) = position.take() {
+            let text = strip_markup(&lines.join(" "));
+            if !text.trim().is_empty() {
+                segments.push(Segment {
+                    section: None,
+                    position: Some(position),
+                    text: text.trim().to_string(),
+                });
+            }
+        }
+        lines.clear();
+    };
+
+    for line in source.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            flush(&mut position, &mut lines);
+        } else if let Some((start, _)) = line.split_once("-->") {
+            flush(&mut position, &mut lines);
+            position = Some(normalize_timestamp(start));
+        } else if position.is_some() {
+            lines.push(line.to_string());
+        }
+    }
+    flush(&mut position, &mut lines);
+
+    segments
+}
+
+/// ASSの`[Events]`セクションの`Dialogue`行をパースする
+fn parse_ass(source: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut in_events = false;
+    // 既定のフィールドの並び
+    let mut fields = vec![
+        "layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect::<Vec<_>>();
+
+    for line in source.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format
+                .split(',')
+                .map(|field| field.trim().to_lowercase())
+                .collect();
+        } else if let Some(dialogue) = line.strip_prefix("Dialogue:") {
+            // 最後のTextフィールドはカンマを含み得る
+            let values = dialogue.splitn(fields.len(), ',').collect::<Vec<_>>();
+            let value = |name: &str| {
+                fields
+                    .iter()
+                    .position(|field| field == name)
+                    .and_then(|i| values.get(i))
+                    .map(|value| value.trim())
+            };
+            let (Some(start), Some(text)) = (value("start"), value("text")) else {
+                continue;
+            };
+            let text = strip_markup(
+                &text
+                    .replace("\\N", " ")
+                    .replace("\\n", " ")
+                    .replace("\\h", " "),
+            );
+            if !text.trim().is_empty() {
+                segments.push(Segment {
+                    section: None,
+                    position: Some(normalize_timestamp(start)),
+                    text: text.trim().to_string(),
+                });
+            }
+        }
+    }
+
+    segments
+}
+
+/// HTMLの字句
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HtmlToken<'a> {
+    /// `<`と`>`の間
+    Tag(&'a str),
+    Text(&'a str),
+}
+
+/// HTML(XML)を字句に分ける．コメントは読み飛ばす．
+pub(crate) fn html_tokens(source: &str) -> Vec<HtmlToken> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment
+                .find("-->")
+                .map_or("", |end| &comment[end + "-->".len()..]);
+        } else if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag.find('>').unwrap_or(tag.len());
+            tokens.push(HtmlToken::Tag(&tag[..end]));
+            rest = tag.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            tokens.push(HtmlToken::Text(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    tokens
+}
+
+/// タグの名前(名前空間の接頭辞を除いた小文字)と終了タグかどうか
+pub(crate) fn tag_name(tag: &str) -> (String, bool) {
+    let (closing, tag) = match tag.strip_prefix('/') {
+        Some(tag) => (true, tag),
+        None => (false, tag),
+    };
+    let name = tag
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default();
+    let name = name.rsplit(':').next().unwrap_or(name);
+    (name.to_lowercase(), closing)
+}
+
+/// タグの属性の値
+pub(crate) fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(i) = rest.find(name) {
+        let before = rest[..i].chars().last();
+        let after = rest[i + name.len()..].trim_start();
+        rest = &rest[i + name.len()..];
+        if !before.map_or(false, char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next()?;
+        let value = if quote == '"' || quote == '\'' {
+            value[1..].split(quote).next()?
+        } else {
+            value.split(char::is_whitespace).next()?
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// HTMLの文字参照を文字に戻す
+pub(crate) fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|end| *end <= 10) else {
+            decoded.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" | "rsquo" | "lsquo" => Some('\''),
+            "ldquo" | "rdquo" => Some('"'),
+            "nbsp" | "ensp" | "emsp" | "thinsp" => Some(' '),
+            "mdash" | "ndash" | "hellip" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// 段落の区切りとなるブロック要素
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "li",
+    "ul",
+    "ol",
+    "dt",
+    "dd",
+    "tr",
+    "td",
+    "th",
+    "table",
+    "blockquote",
+    "section",
+    "article",
+    "aside",
+    "header",
+    "footer",
+    "pre",
+    "hr",
+    "figcaption",
+    "title",
+];
+
+/// 章の区切りとする見出し
+const CHAPTER_HEADINGS: [&str; 2] = ["h1", "h2"];
+
+/// 中身を読み飛ばす要素
+const SKIPPED_ELEMENTS: [&str; 4] = ["script", "style", "head", "nav"];
+
+/// HTMLを見出しで区切った章に分ける
+#[derive(Debug, Clone, Default, PartialEq)]
+struct HtmlDocument {
+    /// `<title>`の中身
+    title: Option<String>,
+    /// 見出しと段落の組のリスト．最初の見出しより前の段落は見出しをNoneとする．
+    chapters: Vec<(Option<String>, Vec<String>)>,
+}
+
+fn parse_html(source: &str) -> HtmlDocument {
+    let mut document = HtmlDocument::default();
+    let mut paragraph = String::new();
+    let mut heading: Option<String> = None;
+    let mut in_title = false;
+    let mut skipping: Option<String> = None;
+
+    let flush = |paragraph: &mut String, document: &mut HtmlDocument| {
+        let text = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            if document.chapters.is_empty() {
+                document.chapters.push((None, Vec::new()));
+            }
+            document.chapters.last_mut().unwrap().1.push(text);
+        }
+        paragraph.clear();
+    };
+
+    for token in html_tokens(source) {
+        match token {
+            HtmlToken::Tag(tag) => {
+                let (name, closing) = tag_name(tag);
+                if let Some(skipped) = skipping.clone() {
+                    if closing && skipped == name {
+                        skipping = None;
+                    }
+                    // <head>の中の<title>だけは読む
+                    if !(name == "title" && skipped == "head") {
+                        continue;
+                    }
+                }
+
+                if name == "title" {
+                    in_title = !closing;
+                } else if SKIPPED_ELEMENTS.contains(&name.as_str()) && !closing {
+                    if !tag.ends_with('/') {
+                        skipping = Some(name);
+                    }
+                } else if CHAPTER_HEADINGS.contains(&name.as_str()) {
+                    flush(&mut paragraph, &mut document);
+                    if closing {
+                        let title = heading
+                            .take()
+                            .unwrap_or_default()
+                            .split_whitespace()
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        document.chapters.push((
+                            (!title.is_empty()).then(|| title.clone()),
+                            if title.is_empty() {
+                                vec![]
+                            } else {
+                                vec![title]
+                            },
+                        ));
+                    } else {
+                        heading = Some(String::new());
+                    }
+                } else if BLOCK_ELEMENTS.contains(&name.as_str())
+                    || (name.len() == 2 && name.starts_with('h'))
+                {
+                    flush(&mut paragraph, &mut document);
+                }
+            }
+            HtmlToken::Text(text) => {
+                let text = decode_entities(text);
+                if in_title {
+                    let title = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if !title.is_empty() {
+                        document.title = Some(title);
+                    }
+                } else if skipping.is_some() {
+                    continue;
+                } else if let Some(heading) = heading.as_mut() {
+                    heading.push(' ');
+                    heading.push_str(&text);
+                } else {
+                    paragraph.push(' ');
+                    paragraph.push_str(&text);
+                }
+            }
+        }
+    }
+    flush(&mut paragraph, &mut document);
+
+    document
+}
+
+/// 章の段落を部分に変換する．位置は章の中での段落の番号とする．
+fn chapter_segments(section: Option<String>, paragraphs: Vec<String>) -> Vec<Segment> {
+    paragraphs
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| Segment {
+            section: section.clone(),
+            position: Some(format!("paragraph {}", i + 1)),
+            text,
+        })
+        .collect()
+}
+
+/// HTMLを見出しごとの章に分ける
+fn html_segments(source: &str) -> Vec<Segment> {
+    parse_html(source)
+        .chapters
+        .into_iter()
+        .flat_map(|(heading, paragraphs)| chapter_segments(heading, paragraphs))
+        .collect()
+}
+
+/// zipアーカイブの中のファイルを文字列として読む
+fn read_zip_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<String, Error> {
+    let mut entry = archive.by_name(name).map_err(|e| {
+        Error::IOError(
+            CustomError::new(format!("Failed to read `{name}` in the EPUB file."), e).into(),
+        )
+    })?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// `%20`のようなURLエンコードを戻す
+fn decode_percent(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// EPUBをspineの順に読み，各文書を1つの章とする．
+///
+/// 章の名前は文書の最初の見出し，無ければ`<title>`，それも無ければファイル名とする．
+fn read_epub(path: &Path) -> Result<Vec<Segment>, Error> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|e| Error::IOError(CustomError::new("Failed to open the EPUB file.", e).into()))?;
+
+    let container = read_zip_entry(&mut archive, "META-INF/container.xml")?;
+    let rootfile = html_tokens(&container)
+        .into_iter()
+        .find_map(|token| match token {
+            HtmlToken::Tag(tag) if tag_name(tag) == ("rootfile".to_string(), false) => {
+                attribute(tag, "full-path")
+            }
+            _ => None,
+        })
+        .ok_or_else(|| {
+            Error::IOError(CustomError::msg("No rootfile is found in the EPUB file.").into())
+        })?;
+    let base = rootfile
+        .rsplit_once('/')
+        .map(|(directory, _)| format!("{directory}/"))
+        .unwrap_or_default();
+
+    let package = read_zip_entry(&mut archive, &rootfile)?;
+    let mut manifest = std::collections::HashMap::new();
+    let mut spine = Vec::new();
+    for token in html_tokens(&package) {
+        let HtmlToken::Tag(tag) = token else {
+            continue;
+        };
+        match tag_name(tag) {
+            (name, false) if name == "item" => {
+                if let (Some(id), Some(href)) = (attribute(tag, "id"), attribute(tag, "href")) {
+                    manifest.insert(id, href);
+                }
+            }
+            (name, false) if name == "itemref" => {
+                if let Some(idref) = attribute(tag, "idref") {
+                    spine.push(idref);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut segments = Vec::new();
+    for idref in spine.iter() {
+        let Some(href) = manifest.get(idref) else {
+            continue;
+        };
+        let href = decode_percent(href.split('#').next().unwrap_or_default());
+        let document = parse_html(&read_zip_entry(&mut archive, &format!("{base}{href}"))?);
+
+        let section = document
+            .chapters
+            .iter()
+            .find_map(|(heading, _)| heading.clone())
+            .or(document.title)
+            .unwrap_or(href);
+        let paragraphs = document
+            .chapters
+            .into_iter()
+            .flat_map(|(_, paragraphs)| paragraphs)
+            .collect::<Vec<_>>();
+        segments.extend(chapter_segments(Some(section), paragraphs));
+    }
+
+    Ok(segments)
+}
+
+/// 文字列を形式に応じて分割する．EPUBはzipアーカイブなので`read_document`で読む．
+pub fn parse_document(source: &str, format: InputFormat) -> Result<Vec<Segment>, Error> {
+    let segments = match format {
+        InputFormat::Text => source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| Segment {
+                section: None,
+                position: Some(format!("line {}", i + 1)),
+                text: line.trim().to_string(),
+            })
+            .collect(),
+        InputFormat::Srt | InputFormat::Vtt => parse_cues(source),
+        InputFormat::Ass => parse_ass(source),
+        InputFormat::Html => html_segments(source),
+        InputFormat::Epub => Err(Error::ArgError(
+            CustomError::msg(
+                "EPUB cannot be parsed from a string. Read EPUB with `read_document`.",
+            )
+            .into(),
+        ))?,
+    };
+    Ok(segments)
+}
+
+/// ファイルを読み込んで分割する．形式の指定が無ければ拡張子から推定する．
+///
+/// HTMLとEPUBは章の区切りを`Segment::section`に残す．
+pub fn read_document(path: &Path, format: Option<InputFormat>) -> Result<Vec<Segment>, Error> {
+    if !path.is_file() {
+        Err(Error::IOError(
+            CustomError::msg(format!(
+                "The input file cannot be found. path: {}",
+                path.display()
+            ))
+            .into(),
+        ))?;
+    }
+    let format = format.unwrap_or_else(|| InputFormat::from_path(path));
+    if format == InputFormat::Epub {
+        return read_epub(path);
+    }
+    let source = std::fs::read_to_string(path)?;
+
+    parse_document(&source, format)
+}
+
+/// ディレクトリ直下の対応する形式のファイルを名前順に読み込む
+pub fn read_directory(directory: &Path) -> Result<Vec<(std::path::PathBuf, Vec<Segment>)>, Error> {
+    if !directory.is_dir() {
+        Err(Error::IOError(
+            CustomError::msg(format!(
+                "The input directory cannot be found. path: {}",
+                directory.display()
+            ))
+            .into(),
+        ))?;
+    }
+
+    let mut paths = std::fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|path| {
+            path.is_file()
+                && path.extension().map_or(false, |extension| {
+                    InputFormat::try_from(extension.to_string_lossy().as_ref()).is_ok()
+                })
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| read_document(&path, None).map(|segments| (path, segments)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(position: &str, text: &str) -> Segment {
+        Segment {
+            section: None,
+            position: Some(position.to_string()),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn strip_markup_keeps_stray_brackets() {
+        assert_eq!(strip_markup("<i>Hello</i>, {\\an8}world"), "Hello , world");
+        assert_eq!(strip_markup("a < b and c > d"), "a < b and c > d");
+        assert_eq!(strip_markup("x <= 3"), "x <= 3");
+        assert_eq!(
+            strip_markup("<font color=\"red\">an unclosed {brace"),
+            "an unclosed {brace"
+        );
+        assert_eq!(strip_markup("I <3 you"), "I <3 you");
+    }
+
+    #[test]
+    fn normalize_timestamp_pads_fraction() {
+        assert_eq!(normalize_timestamp("00:01:02,345"), "00:01:02.345");
+        assert_eq!(normalize_timestamp("0:01:02.34"), "00:01:02.340");
+        assert_eq!(normalize_timestamp("01:02.5"), "00:01:02.500");
+        assert_eq!(normalize_timestamp("00:00:01"), "00:00:01.000");
+    }
+
+    #[test]
+    fn parse_srt() {
+        let source =
+            "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Don't</i> go.\r\nNot yet.\r\n\r\n\
+                      2\r\n00:00:03,000 --> 00:00:04,000\r\nIf a < b, stop.\r\n\r\n\
+                      3\r\n00:00:05,000 --> 00:00:06,000\r\n<i></i>\r\n";
+        assert_eq!(
+            parse_document(source, InputFormat::Srt).unwrap(),
+            vec![
+                cue("00:00:01.000", "Don't go. Not yet."),
+                cue("00:00:03.000", "If a < b, stop."),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_vtt() {
+        let source = "WEBVTT\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:02.000 align:start\n\
+                      <v Roger>Hi <00:01.500><c.yellow>there</c></v>\n\n\
+                      00:00:03.250 --> 00:00:04.000\n- Yes.\n- No.\n";
+        assert_eq!(
+            parse_document(source, InputFormat::Vtt).unwrap(),
+            vec![
+                cue("00:00:01.000", "Hi there"),
+                cue("00:00:03.250", "- Yes. - No."),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ass_dialogue() {
+        let source = "[Script Info]\nTitle: test\n\n[V4+ Styles]\nFormat: Name, Fontname\n\n\
+                      [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                      Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,ignored\n\
+                      Dialogue: 0,0:00:01.50,0:00:03.00,Default,,0,0,0,,{\\i1}Well,{\\i0} hello\\Nworld\n\
+                      Dialogue: 0,0:01:02.05,0:01:03.00,Default,,0,0,0,,{\\an8}\n";
+        assert_eq!(
+            parse_document(source, InputFormat::Ass).unwrap(),
+            vec![cue("00:00:01.500", "Well, hello world")]
+        );
+    }
+
+    #[test]
+    fn parse_ass_with_custom_format() {
+        let source =
+            "[Events]\nFormat: Start, End, Text\nDialogue: 0:00:10.00,0:00:11.00,a, b, c\n";
+        assert_eq!(
+            parse_document(source, InputFormat::Ass).unwrap(),
+            vec![cue("00:00:10.000", "a, b, c")]
+        );
+    }
+}
@@ -19,6 +19,10 @@ pub enum Error {
     /// データフレームに関するエラー．
     #[error("DataFrameError: Error related on dataframe occurred. {0}")]
     DataFrameError(Box<dyn std::error::Error>),
+
+    /// 設定ファイルに関するエラー．
+    #[error("ConfigError: Error related on config file occurred. {0}")]
+    ConfigError(Box<dyn std::error::Error>),
 }
 
 impl std::fmt::Debug for Error {
@@ -0,0 +1,294 @@
+use crate::batch::{f64_column, string_column};
+use crate::gloss::Glossary;
+use crate::{BandRange, CustomError, Error, SheetType};
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use datafusion::prelude::{DataFrame, SessionContext};
+use serde::{Deserialize, Serialize};
+
+/// テンプレートで使えるフィールド
+pub const TEMPLATE_FIELDS: [&str; 6] = ["lemma", "PoS", "rank", "band", "forms", "gloss"];
+
+/// Ankiのカードの表面と裏面のテンプレート．
+///
+/// `{lemma}`のようにフィールド名を波括弧で囲んで値を埋め込む．値はHTMLとしてエスケープされ，
+/// テンプレート自体にはHTMLを書ける．波括弧そのものは`{{`と`}}`で表す．
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardTemplate {
+    pub front: String,
+    pub back: String,
+}
+
+impl Default for CardTemplate {
+    fn default() -> Self {
+        Self {
+            front: "{lemma}".to_string(),
+            back: "{PoS} #{rank}<br>{forms}<br>{gloss}".to_string(),
+        }
+    }
+}
+
+impl CardTemplate {
+    /// テンプレートを作る．未知のフィールドや閉じていない波括弧はエラー．
+    pub fn new(front: &str, back: &str) -> Result<Self, CustomError> {
+        let template = Self {
+            front: front.to_string(),
+            back: back.to_string(),
+        };
+        template.render(&Card::default())?;
+        Ok(template)
+    }
+
+    /// カードの表面と裏面
+    pub fn render(&self, card: &Card) -> Result<(String, String), CustomError> {
+        Ok((
+            render_template(&self.front, card)?,
+            render_template(&self.back, card)?,
+        ))
+    }
+}
+
+/// カード1枚分のフィールド
+#[derive(Debug, Clone, Default)]
+pub struct Card {
+    pub rank: i64,
+    pub lemma: String,
+    pub pos: String,
+    /// 語形(頻度の降順)
+    pub forms: Vec<String>,
+    pub gloss: Option<String>,
+}
+
+impl Card {
+    fn field(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "lemma" => self.lemma.clone(),
+            "PoS" => self.pos.clone(),
+            "rank" => self.rank.to_string(),
+            "band" => BandRange::band_of(self.rank).to_string(),
+            "forms" => self.forms.join(", "),
+            "gloss" => self.gloss.clone().unwrap_or_default(),
+            _ => return None,
+        };
+        Some(escape_html(&value))
+    }
+
+    /// カードのタグ
+    fn tags(&self) -> String {
+        let mut tags = vec![
+            "coca".to_string(),
+            format!("band_{}", BandRange::band_of(self.rank)),
+        ];
+        if !self.pos.is_empty() {
+            tags.push(format!("pos_{}", self.pos));
+        }
+        tags.join(" ")
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_template(template: &str, card: &Card) -> Result<String, CustomError> {
+    let mut rendered = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                rendered.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                rendered.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => Err(CustomError::msg(format!(
+                            "Unclosed field in the card template. template: {template}"
+                        )))?,
+                    }
+                }
+                let value = card.field(name.trim()).ok_or_else(|| {
+                    CustomError::msg(format!(
+                        "Unknown field in the card template. Choose fields in {TEMPLATE_FIELDS:?}. field: {name}"
+                    ))
+                })?;
+                rendered.push_str(&value);
+            }
+            _ => rendered.push(c),
+        }
+    }
+    // 改行とタブはフィールドの区切りにならないようにする
+    Ok(rendered
+        .replace('\t', " ")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>"))
+}
+
+/// 登録済みのwordFormsシートからレンマ(と品詞)ごとの語形を頻度の降順で集める．
+/// レンマと同じ語形は含めない．
+pub async fn load_forms(
+    ctx: &SessionContext,
+) -> Result<HashMap<(String, String), Vec<String>>, Error> {
+    let word_forms = SheetType::Third.table_name();
+    let schema = ctx.table(word_forms.as_str()).await?.schema().clone();
+    let order = if schema.has_column_with_unqualified_name("wordFreq") {
+        r#""wordFreq" DESC, word"#
+    } else {
+        "word"
+    };
+
+    let sql = format!(r#"SELECT lemma, "PoS", word FROM {word_forms} ORDER BY {order}"#);
+    let batches = ctx.sql(&sql).await?.collect().await?;
+    let lemmas = string_column(&batches, "lemma")?;
+    let pos_list = string_column(&batches, "PoS")?;
+    let words = string_column(&batches, "word")?;
+
+    let mut forms: HashMap<(String, String), Vec<String>> = HashMap::new();
+    for ((lemma, pos), word) in lemmas.into_iter().zip(pos_list).zip(words) {
+        let (Some(lemma), Some(word)) = (lemma, word) else {
+            continue;
+        };
+        let word = word.trim().to_lowercase();
+        if word == lemma {
+            continue;
+        }
+        let entry = forms.entry((lemma, pos.unwrap_or_default())).or_default();
+        if !entry.contains(&word) {
+            entry.push(word);
+        }
+    }
+    Ok(forms)
+}
+
+/// クエリ結果(rank，lemma，PoSのカラムを持つ)からカードを作る
+pub async fn anki_cards(
+    df: DataFrame,
+    forms: &HashMap<(String, String), Vec<String>>,
+    glossary: Option<&Glossary>,
+) -> Result<Vec<Card>, Error> {
+    let batches = df.collect().await?;
+    let ranks = f64_column(&batches, "rank")?;
+    let lemmas = string_column(&batches, "lemma")?;
+    let pos_list = string_column(&batches, "PoS")?;
+
+    let cards = ranks
+        .into_iter()
+        .zip(lemmas)
+        .zip(pos_list)
+        .filter_map(|((rank, lemma), pos)| {
+            let lemma = lemma?;
+            let pos = pos.unwrap_or_default();
+            Some(Card {
+                rank: rank.map(|rank| rank.round() as i64).unwrap_or_default(),
+                forms: forms
+                    .get(&(lemma.clone(), pos.clone()))
+                    .cloned()
+                    .unwrap_or_default(),
+                gloss: glossary.and_then(|glossary| glossary.lookup(&lemma, &pos)),
+                lemma,
+                pos,
+            })
+        })
+        .collect();
+    Ok(cards)
+}
+
+/// Ankiで読み込めるタブ区切りのテキストを書き込む．
+///
+/// 各行は表面，裏面，タグの3列で，ファイルの先頭に区切り文字などを指定するヘッダーを付ける．
+pub fn write_anki<W: Write>(
+    writer: &mut W,
+    cards: &[Card],
+    template: &CardTemplate,
+    deck: Option<&str>,
+) -> Result<(), Error> {
+    writeln!(writer, "#separator:tab")?;
+    writeln!(writer, "#html:true")?;
+    writeln!(writer, "#columns:Front\tBack\tTags")?;
+    writeln!(writer, "#tags column:3")?;
+    if let Some(deck) = deck {
+        writeln!(writer, "#deck:{deck}")?;
+    }
+    for card in cards.iter() {
+        let (front, back) = template
+            .render(card)
+            .map_err(|e| Error::ArgError(e.into()))?;
+        writeln!(writer, "{front}\t{back}\t{}", card.tags())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card() -> Card {
+        Card {
+            rank: 1500,
+            lemma: "saw".to_string(),
+            pos: "n".to_string(),
+            forms: vec!["saws".to_string()],
+            gloss: Some("<のこぎり> & \"鋸\"\tsaw\nblade".to_string()),
+        }
+    }
+
+    #[test]
+    fn render_escapes_values_and_flattens_lines() {
+        let template = CardTemplate::new(
+            "{lemma} {{{PoS}}}",
+            "#{ rank } (band {band})\n{forms}\r\n{gloss}",
+        )
+        .unwrap();
+        let (front, back) = template.render(&card()).unwrap();
+        assert_eq!(front, "saw {n}");
+        assert_eq!(
+            back,
+            "#1500 (band 2)<br>saws<br>&lt;のこぎり&gt; &amp; &quot;鋸&quot; saw<br>blade"
+        );
+    }
+
+    #[test]
+    fn template_errors() {
+        let error = CardTemplate::new("{lemma", "{gloss}").unwrap_err();
+        assert!(error.to_string().contains("Unclosed field"));
+
+        let error = CardTemplate::new("{lemma}", "{meaning}").unwrap_err();
+        assert!(error.to_string().contains("Unknown field"));
+        assert!(error.to_string().contains("field: meaning"));
+    }
+
+    #[test]
+    fn write_anki_keeps_three_columns() {
+        let mut buffer = Vec::new();
+        write_anki(
+            &mut buffer,
+            &[card()],
+            &CardTemplate::default(),
+            Some("COCA"),
+        )
+        .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[4], "#deck:COCA");
+        let columns = lines[5].split('\t').collect::<Vec<_>>();
+        assert_eq!(columns.len(), 3);
+        assert_eq!(columns[0], "saw");
+        assert_eq!(columns[2], "coca band_2 pos_n");
+    }
+}
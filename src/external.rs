@@ -0,0 +1,173 @@
+use crate::gloss::GLOSS_TABLE_NAME;
+use crate::known::KNOWN_TABLE_NAME;
+use crate::subgenre::SUBGENRE_TABLE_NAME;
+use crate::views::{LEMMA_GENRE_VIEW_NAME, LEMMA_SUBGENRE_VIEW_NAME};
+use crate::{CustomError, Error, SheetType};
+
+use std::path::PathBuf;
+
+use datafusion::prelude::{CsvReadOptions, NdJsonReadOptions, ParquetReadOptions, SessionContext};
+
+/// 外部ファイルの形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExternalFormat {
+    Csv,
+    /// タブ区切り
+    Tsv,
+    Parquet,
+    /// 1行に1つのJSONオブジェクト(NDJSON)
+    Json,
+}
+
+impl ExternalFormat {
+    /// 拡張子から形式を決める
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "tsv" | "tab" => Some(Self::Tsv),
+            "parquet" => Some(Self::Parquet),
+            "json" | "jsonl" | "ndjson" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// 組み込みのテーブル名．外部のテーブルで上書きしないようにする．
+fn reserved_table_names() -> Vec<String> {
+    [
+        SheetType::First,
+        SheetType::Second,
+        SheetType::Third,
+        SheetType::Fourth,
+    ]
+    .iter()
+    .map(SheetType::table_name)
+    .chain(
+        [
+            KNOWN_TABLE_NAME,
+            GLOSS_TABLE_NAME,
+            SUBGENRE_TABLE_NAME,
+            LEMMA_GENRE_VIEW_NAME,
+            LEMMA_SUBGENRE_VIEW_NAME,
+        ]
+        .into_iter()
+        .map(str::to_string),
+    )
+    .collect()
+}
+
+/// 名前を付けて登録する外部のファイル(発音辞書や習得年齢，具体性の評定値など)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalTable {
+    /// テーブル名．sqlでそのまま書けるように小文字の英数字と`_`に限る．
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl ExternalTable {
+    pub fn new(name: &str, path: &str) -> Result<Self, CustomError> {
+        let name = name.trim();
+        let valid = name
+            .chars()
+            .next()
+            .map_or(false, |c| c.is_ascii_lowercase() || c == '_')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            Err(CustomError::msg(format!(
+                "Invalid table name. Use lowercase letters, digits and underscores. name: {name}"
+            )))?;
+        }
+        if reserved_table_names()
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(name))
+        {
+            Err(CustomError::msg(format!(
+                "The table name is reserved for a built-in table. Use another name. name: {name}"
+            )))?;
+        }
+        let path = path.trim();
+        if path.is_empty() {
+            Err(CustomError::msg(format!(
+                "Empty path for the table. name: {name}"
+            )))?;
+        }
+        Ok(Self {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+
+    /// 拡張子から決めた形式
+    pub fn format(&self) -> Result<ExternalFormat, CustomError> {
+        self.path
+            .extension()
+            .and_then(|extension| ExternalFormat::from_extension(&extension.to_string_lossy()))
+            .ok_or_else(|| {
+                CustomError::msg(format!(
+                    "Unknown file format. Use csv, tsv, parquet or json (ndjson) files. path: {}",
+                    self.path.display()
+                ))
+            })
+    }
+
+    /// ファイルをテーブルとして登録する
+    pub async fn register(&self, ctx: &SessionContext) -> Result<(), Error> {
+        let format = self.format().map_err(|e| Error::ArgError(e.into()))?;
+        if !self.path.is_file() {
+            Err(Error::IOError(
+                CustomError::msg(format!(
+                    "The file for the table `{}` cannot be found. path: {}",
+                    self.name,
+                    self.path.display()
+                ))
+                .into(),
+            ))?;
+        }
+
+        // ファイルの拡張子と読み込みの設定の拡張子が異なると読み込まれない
+        let extension = format!(
+            ".{}",
+            self.path.extension().unwrap_or_default().to_string_lossy()
+        );
+        let path = self.path.to_string_lossy();
+        match format {
+            ExternalFormat::Csv => {
+                let options = CsvReadOptions::new().file_extension(&extension);
+                ctx.register_csv(&self.name, &path, options).await?;
+            }
+            ExternalFormat::Tsv => {
+                let options = CsvReadOptions::new()
+                    .delimiter(b'\t')
+                    .file_extension(&extension);
+                ctx.register_csv(&self.name, &path, options).await?;
+            }
+            ExternalFormat::Parquet => {
+                let options = ParquetReadOptions {
+                    file_extension: &extension,
+                    ..Default::default()
+                };
+                ctx.register_parquet(&self.name, &path, options).await?;
+            }
+            ExternalFormat::Json => {
+                let options = NdJsonReadOptions::default().file_extension(&extension);
+                ctx.register_json(&self.name, &path, options).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for ExternalTable {
+    type Error = CustomError;
+    /// `name=path`の形式
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (name, path) = value.split_once('=').ok_or_else(|| {
+            CustomError::msg(format!(
+                "Invalid table. Specify it as name=path (e.g. cmu=cmudict.csv). value: {value}"
+            ))
+        })?;
+        Self::new(name, path)
+    }
+}
@@ -0,0 +1,209 @@
+use crate::keyness::genre_sql;
+use crate::stats::band_sql;
+use crate::subgenre::{registered_subgenre_columns, subgenre_sql};
+use crate::{BandRange, CustomError, Error, Genre, SheetType};
+
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// ジャンルに特有の語彙リストを抽出する条件
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractCriteria {
+    /// 対象のジャンル
+    pub target: Genre,
+    /// 比較する一般的なジャンル．空なら対象以外の全てのジャンル．
+    pub references: Vec<Genre>,
+    /// 対象と参照の100万語あたりの頻度の比の下限
+    pub min_ratio: f64,
+    /// 対象での100万語あたりの頻度の下限
+    pub min_per_mil: Option<f64>,
+    /// 期待頻度の`expected_share`倍以上現れるサブジャンルの割合の下限．Noneなら判定しない．
+    pub min_coverage: Option<f64>,
+    pub expected_share: f64,
+    /// 除外する一般的な頻度帯
+    pub exclude_bands: Option<BandRange>,
+    /// 全体での分散度(disp)の下限
+    pub min_disp: Option<f64>,
+}
+
+impl ExtractCriteria {
+    /// AWLのような語彙リストの既定の条件．第1帯の一般語彙を除外し，
+    /// 対象のサブジャンルの70%以上で期待頻度の20%以上現れる語に限る．
+    pub fn new(target: Genre) -> Self {
+        Self {
+            target,
+            references: vec![],
+            min_ratio: 1.5,
+            min_per_mil: None,
+            min_coverage: Some(0.7),
+            expected_share: 0.2,
+            exclude_bands: Some(BandRange { start: 1, end: 1 }),
+            min_disp: None,
+        }
+    }
+}
+
+/// 対象のジャンルで参照のジャンルより多く使われ，サブジャンルに広く分散するレンマを
+/// 対象での頻度の降順で返す．
+///
+/// 対象と参照の頻度比(ratio)，対象のサブジャンルのうち期待頻度の`expected_share`倍以上
+/// 現れるものの割合(subgenre_coverage)を求める．サブジャンルの判定はsubgenresシートと
+/// lemma・PoSで結合して行う．
+pub async fn extract(ctx: &SessionContext, criteria: &ExtractCriteria) -> Result<DataFrame, Error> {
+    let target = criteria.target;
+    if criteria.references.contains(&target) {
+        Err(Error::ArgError(
+            CustomError::msg("The reference genres must not contain the target genre.").into(),
+        ))?;
+    }
+
+    let references = if criteria.references.is_empty() {
+        Genre::all()
+            .into_iter()
+            .filter(|genre| *genre != target)
+            .collect::<Vec<_>>()
+    } else {
+        criteria.references.clone()
+    };
+    let reference_sql = references
+        .iter()
+        .map(|genre| genre_sql(*genre))
+        .collect::<Vec<_>>()
+        .join(" + ");
+
+    let lemmas = SheetType::First.table_name();
+    let schema = ctx.table(lemmas.as_str()).await?.schema().clone();
+    let has_disp = schema.has_column_with_unqualified_name("disp");
+    if criteria.min_disp.is_some() && !has_disp {
+        Err(Error::ArgError(
+            CustomError::msg("The lemmas sheet has no disp column.").into(),
+        ))?;
+    }
+    let disp = if has_disp {
+        ", CAST(disp AS DOUBLE) AS disp"
+    } else {
+        ""
+    };
+
+    let mut conditions = vec![
+        "a > 0".to_string(),
+        format!(
+            "(b = 0 OR (a * 1000000.0 / c) / (b * 1000000.0 / d) >= {})",
+            criteria.min_ratio
+        ),
+    ];
+    if let Some(min_per_mil) = criteria.min_per_mil {
+        conditions.push(format!("a * 1000000.0 / c >= {min_per_mil}"));
+    }
+    if let Some(min_disp) = criteria.min_disp {
+        conditions.push(format!("disp >= {min_disp}"));
+    }
+    if let Some(bands) = criteria.exclude_bands {
+        conditions.push(format!(
+            "band NOT BETWEEN {} AND {}",
+            bands.start, bands.end
+        ));
+    }
+
+    // サブジャンルへの分散
+    let (coverage_ctes, coverage_join, coverage_select) = match criteria.min_coverage {
+        Some(min_coverage) => {
+            let columns = registered_subgenre_columns(ctx)
+                .await?
+                .into_iter()
+                .filter(|column| column.genre == target)
+                .collect::<Vec<_>>();
+            if columns.is_empty() {
+                Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "No subgenre columns are found for the genre `{}`.",
+                        target.column_name()
+                    ))
+                    .into(),
+                ))?;
+            }
+
+            let totals = columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| format!("SUM({}) AS total_{i}", subgenre_sql(column)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let genre_freq = columns
+                .iter()
+                .map(subgenre_sql)
+                .collect::<Vec<_>>()
+                .join(" + ");
+            let genre_total = (0..columns.len())
+                .map(|i| format!("total_{i}"))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            let covered = columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    format!(
+                        "CASE WHEN {} * 1000000.0 / total_{i} >= {} * ({genre_freq}) * 1000000.0 / ({genre_total}) THEN 1.0 ELSE 0.0 END",
+                        subgenre_sql(column),
+                        criteria.expected_share
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" + ");
+
+            conditions.push(format!("subgenre_coverage >= {min_coverage}"));
+
+            (
+                format!(
+                    r#", subgenre_totals AS (
+                        SELECT {totals} FROM {subgenres}
+                    ), coverage AS (
+                        SELECT lemma, "PoS", MAX(({covered}) / {count}.0) AS subgenre_coverage
+                        FROM {subgenres} CROSS JOIN subgenre_totals
+                        GROUP BY lemma, "PoS"
+                    )"#,
+                    subgenres = SheetType::Second.table_name(),
+                    count = columns.len(),
+                ),
+                r#" LEFT JOIN coverage ON counts.lemma = coverage.lemma AND counts."PoS" = coverage."PoS""#
+                    .to_string(),
+                ", subgenre_coverage".to_string(),
+            )
+        }
+        None => (String::new(), String::new(), String::new()),
+    };
+
+    let sql = format!(
+        r#"WITH counts AS (
+            SELECT "rank", lemma, "PoS", {band} AS band{disp}, {target_sql} AS a, {reference_sql} AS b
+            FROM {lemmas}
+        ), totals AS (
+            SELECT SUM(a) AS c, SUM(b) AS d FROM counts
+        ){coverage_ctes}, scored AS (
+            SELECT counts."rank", counts.lemma, counts."PoS", band{disp_select}, a, b, c, d{coverage_select}
+            FROM counts CROSS JOIN totals{coverage_join}
+        )
+        SELECT "rank", lemma, "PoS", band{disp_select},
+            a AS target_freq,
+            a * 1000000.0 / c AS target_per_mil,
+            b * 1000000.0 / d AS reference_per_mil,
+            CASE WHEN b > 0 THEN (a * 1000000.0 / c) / (b * 1000000.0 / d) END AS ratio{coverage_select}
+        FROM scored
+        WHERE {conditions}
+        ORDER BY target_freq DESC, "rank" ASC"#,
+        band = band_sql(),
+        target_sql = genre_sql(target),
+        disp_select = if has_disp { ", disp" } else { "" },
+        conditions = conditions.join(" AND "),
+    );
+
+    Ok(ctx.sql(&sql).await?)
+}
+
+/// 参照のジャンルのリストをパースする
+pub fn parse_references(source: &str) -> Result<Vec<Genre>, CustomError> {
+    source
+        .split(',')
+        .filter(|genre| !genre.trim().is_empty())
+        .map(Genre::try_from)
+        .collect()
+}
@@ -0,0 +1,671 @@
+use crate::{CustomError, Error};
+
+use datafusion::common::DFSchema;
+use datafusion::logical_expr;
+use datafusion::prelude::Expr;
+
+/// `--where`で指定するフィルター式をパースし，DataFusionの`Expr`に変換する．
+///
+/// 文法の概略:
+///
+/// ```text
+/// or_expr   := and_expr ("or" and_expr)*
+/// and_expr  := not_expr ("and" not_expr)*
+/// not_expr  := "not" not_expr | predicate
+/// predicate := additive ( cmp_op additive
+///                       | ["not"] "in" "(" item ("," item)* ")"
+///                       | ["not"] "like" string
+///                       | ["not"] "between" additive "and" additive
+///                       | "is" ["not"] "null" )?
+/// additive  := term (("+" | "-") term)*
+/// term      := unary (("*" | "/") unary)*
+/// unary     := "-" unary | primary
+/// primary   := number | 'string' | column | "(" or_expr ")"
+/// ```
+///
+/// カラム名はスキーマと照合し，存在しない場合は候補を含むエラーを返す．
+/// `in`のリスト内の裸の識別子は文字列として扱う(`PoS in (n, j)`)．
+pub fn parse_filter(source: &str, schema: &DFSchema) -> Result<Expr, Error> {
+    let tokens = tokenize(source)?;
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| field.name().to_owned())
+        .collect::<Vec<_>>();
+
+    let mut parser = Parser {
+        source,
+        tokens,
+        position: 0,
+        columns,
+    };
+
+    let expr = parser.or_expr()?;
+
+    let token = parser.peek();
+    if token.kind != TokenKind::End {
+        return Err(parser.error_at(
+            token.position,
+            "unexpected token after the end of expression.",
+        ));
+    }
+
+    Ok(expr)
+}
+
+// -------------------------------------------------------------------------------------------------
+// トークナイザ
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    /// 識別子またはキーワード
+    Ident(String),
+    /// ダブルクォートで囲まれた識別子(キーワードとして扱わない)
+    QuotedIdent(String),
+    /// 数値リテラル
+    Number(String),
+    /// シングルクォートで囲まれた文字列リテラル
+    Str(String),
+    /// 演算子・括弧など
+    Symbol(&'static str),
+    /// 入力の終端
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// 入力中の文字位置(0始まり)
+    position: usize,
+}
+
+const SYMBOLS: [&str; 15] = [
+    "==", "!=", "<>", "<=", ">=", "=", "<", ">", "(", ")", ",", "+", "-", "*", "/",
+];
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '%' || c == '#'
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+
+        // 文字列リテラル・クォートされた識別子
+        if c == '\'' || c == '"' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some(&ch) if ch == c => {
+                        // 同じクォートの連続はエスケープとみなす
+                        if chars.get(i + 1) == Some(&c) {
+                            value.push(c);
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    Some(&ch) => {
+                        value.push(ch);
+                        i += 1;
+                    }
+                    None => {
+                        return Err(filter_error(source, start, "unterminated quotation."));
+                    }
+                }
+            }
+            let kind = if c == '\'' {
+                TokenKind::Str(value)
+            } else {
+                TokenKind::QuotedIdent(value)
+            };
+            tokens.push(Token {
+                kind,
+                position: start,
+            });
+            continue;
+        }
+
+        // 数値リテラル
+        if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|ch| ch.is_ascii_digit()))
+        {
+            let mut value = String::new();
+            let mut has_dot = false;
+            while let Some(&ch) = chars.get(i) {
+                if ch.is_ascii_digit() {
+                    value.push(ch);
+                } else if ch == '.' && !has_dot {
+                    has_dot = true;
+                    value.push(ch);
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+            // 数字の直後に識別子文字が続く場合は識別子とみなさずエラーにする
+            if chars.get(i).is_some_and(|&ch| is_ident_char(ch)) {
+                return Err(filter_error(source, start, "invalid number literal."));
+            }
+            tokens.push(Token {
+                kind: TokenKind::Number(value),
+                position: start,
+            });
+            continue;
+        }
+
+        // 識別子・キーワード
+        if is_ident_char(c) {
+            let mut value = String::new();
+            while let Some(&ch) = chars.get(i) {
+                if !is_ident_char(ch) {
+                    break;
+                }
+                value.push(ch);
+                i += 1;
+            }
+            tokens.push(Token {
+                kind: TokenKind::Ident(value),
+                position: start,
+            });
+            continue;
+        }
+
+        // 記号
+        let symbol = SYMBOLS.iter().find(|symbol| {
+            symbol
+                .chars()
+                .enumerate()
+                .all(|(offset, ch)| chars.get(i + offset) == Some(&ch))
+        });
+        match symbol {
+            Some(symbol) => {
+                i += symbol.chars().count();
+                tokens.push(Token {
+                    kind: TokenKind::Symbol(symbol),
+                    position: start,
+                });
+            }
+            None => {
+                return Err(filter_error(
+                    source,
+                    start,
+                    format!("unexpected character `{c}`."),
+                ));
+            }
+        }
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: chars.len(),
+    });
+
+    Ok(tokens)
+}
+
+/// 位置を示すキャレット付きのエラーを作成する
+fn filter_error<D: std::fmt::Display>(source: &str, position: usize, msg: D) -> Error {
+    Error::ArgError(
+        CustomError::msg(format!(
+            "Invalid filter expression at position {}: {msg}\n    {source}\n    {}^",
+            position + 1,
+            " ".repeat(position)
+        ))
+        .into(),
+    )
+}
+
+// -------------------------------------------------------------------------------------------------
+// パーサー
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    /// 照合に用いるカラム名
+    columns: Vec<String>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Token {
+        self.tokens[self.position].clone()
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek();
+        if token.kind != TokenKind::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error_at<D: std::fmt::Display>(&self, position: usize, msg: D) -> Error {
+        filter_error(self.source, position, msg)
+    }
+
+    /// 次のトークンが指定したキーワードなら消費してtrueを返す
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match &self.peek().kind {
+            TokenKind::Ident(ident) if ident.eq_ignore_ascii_case(keyword) => {
+                self.advance();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 次のトークンが指定したキーワードか確認する(消費しない)
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Ident(ident) if ident.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        match self.peek().kind {
+            TokenKind::Symbol(s) if s == symbol => {
+                self.advance();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), Error> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            let token = self.peek();
+            Err(self.error_at(token.position, format!("expected `{symbol}`.")))
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and_expr()?;
+        while self.eat_keyword("or") {
+            expr = expr.or(self.and_expr()?);
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.not_expr()?;
+        while self.eat_keyword("and") {
+            expr = expr.and(self.not_expr()?);
+        }
+        Ok(expr)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, Error> {
+        if self.eat_keyword("not") {
+            Ok(logical_expr::not(self.not_expr()?))
+        } else {
+            self.predicate()
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Expr, Error> {
+        let left = self.additive()?;
+
+        // 比較演算子
+        let token = self.peek();
+        if let TokenKind::Symbol(symbol) = token.kind {
+            let compare: Option<fn(Expr, Expr) -> Expr> = match symbol {
+                "=" | "==" => Some(Expr::eq),
+                "!=" | "<>" => Some(Expr::not_eq),
+                "<" => Some(Expr::lt),
+                "<=" => Some(Expr::lt_eq),
+                ">" => Some(Expr::gt),
+                ">=" => Some(Expr::gt_eq),
+                _ => None,
+            };
+            if let Some(compare) = compare {
+                self.advance();
+                let right = self.additive()?;
+                return Ok(compare(left, right));
+            }
+        }
+
+        // is [not] null
+        if self.eat_keyword("is") {
+            let negated = self.eat_keyword("not");
+            if !self.eat_keyword("null") {
+                let token = self.peek();
+                return Err(self.error_at(token.position, "expected `null` after `is`."));
+            }
+            return Ok(if negated {
+                left.is_not_null()
+            } else {
+                left.is_null()
+            });
+        }
+
+        // [not] in / like / between
+        let negated = if self.peek_keyword("not") {
+            self.advance();
+            true
+        } else {
+            false
+        };
+
+        if self.eat_keyword("in") {
+            self.expect_symbol("(")?;
+            let mut list = vec![self.list_item()?];
+            while self.eat_symbol(",") {
+                list.push(self.list_item()?);
+            }
+            self.expect_symbol(")")?;
+            return Ok(logical_expr::in_list(left, list, negated));
+        }
+
+        if self.eat_keyword("like") {
+            let token = self.advance();
+            let pattern = match token.kind {
+                TokenKind::Str(pattern) => pattern,
+                _ => {
+                    return Err(self.error_at(
+                        token.position,
+                        "expected a quoted pattern after `like` (e.g. 'un%').",
+                    ))
+                }
+            };
+            let pattern = logical_expr::lit(pattern);
+            return Ok(if negated {
+                left.not_like(pattern)
+            } else {
+                left.like(pattern)
+            });
+        }
+
+        if self.eat_keyword("between") {
+            let low = self.additive()?;
+            if !self.eat_keyword("and") {
+                let token = self.peek();
+                return Err(self.error_at(token.position, "expected `and` in `between`."));
+            }
+            let high = self.additive()?;
+            return Ok(if negated {
+                left.not_between(low, high)
+            } else {
+                left.between(low, high)
+            });
+        }
+
+        if negated {
+            let token = self.peek();
+            return Err(self.error_at(
+                token.position,
+                "expected `in`, `like` or `between` after `not`.",
+            ));
+        }
+
+        Ok(left)
+    }
+
+    /// inのリストの要素．裸の識別子は文字列リテラルとして扱う
+    fn list_item(&mut self) -> Result<Expr, Error> {
+        let token = self.peek();
+        match token.kind {
+            TokenKind::Ident(ident) => {
+                self.advance();
+                Ok(logical_expr::lit(ident))
+            }
+            _ => self.additive(),
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.term()?;
+        loop {
+            if self.eat_symbol("+") {
+                expr = expr + self.term()?;
+            } else if self.eat_symbol("-") {
+                expr = expr - self.term()?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        loop {
+            if self.eat_symbol("*") {
+                expr = expr * self.unary()?;
+            } else if self.eat_symbol("/") {
+                expr = expr / self.unary()?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        if self.eat_symbol("-") {
+            Ok(Expr::Negative(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let token = self.advance();
+        match token.kind {
+            TokenKind::Number(number) => {
+                if number.contains('.') {
+                    let value = number
+                        .parse::<f64>()
+                        .map_err(|_| self.error_at(token.position, "invalid number literal."))?;
+                    Ok(logical_expr::lit(value))
+                } else {
+                    let value = number
+                        .parse::<i64>()
+                        .map_err(|_| self.error_at(token.position, "invalid number literal."))?;
+                    Ok(logical_expr::lit(value))
+                }
+            }
+            TokenKind::Str(value) => Ok(logical_expr::lit(value)),
+            TokenKind::Ident(ident) => {
+                if ["and", "or", "not", "in", "like", "between", "is", "null"]
+                    .iter()
+                    .any(|keyword| ident.eq_ignore_ascii_case(keyword))
+                {
+                    return Err(
+                        self.error_at(token.position, format!("unexpected keyword `{ident}`."))
+                    );
+                }
+                self.column(&ident, token.position)
+            }
+            TokenKind::QuotedIdent(ident) => self.column(&ident, token.position),
+            TokenKind::Symbol("(") => {
+                let expr = self.or_expr()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            TokenKind::Symbol(symbol) => {
+                Err(self.error_at(token.position, format!("unexpected `{symbol}`.")))
+            }
+            TokenKind::End => Err(self.error_at(token.position, "unexpected end of expression.")),
+        }
+    }
+
+    /// カラム名を検証して列の式を返す
+    fn column(&self, name: &str, position: usize) -> Result<Expr, Error> {
+        if self.columns.iter().any(|column| column == name) {
+            return Ok(logical_expr::col(format!(r#""{name}""#)));
+        }
+
+        // 大文字小文字のみ異なる場合は一意であれば許容する
+        let case_insensitive = self
+            .columns
+            .iter()
+            .filter(|column| column.eq_ignore_ascii_case(name))
+            .collect::<Vec<_>>();
+        if case_insensitive.len() == 1 {
+            return Ok(logical_expr::col(format!(r#""{}""#, case_insensitive[0])));
+        }
+
+        let suggestion = self
+            .columns
+            .iter()
+            .map(|column| {
+                (
+                    edit_distance(&column.to_lowercase(), &name.to_lowercase()),
+                    column,
+                )
+            })
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance);
+
+        let msg = match suggestion {
+            Some((_, column)) => format!("unknown column `{name}`. Did you mean `{column}`?"),
+            None => format!(
+                "unknown column `{name}`. Available columns: {}. Quote string values like 'v'.",
+                self.columns.join(", ")
+            ),
+        };
+        Err(self.error_at(position, msg))
+    }
+}
+
+/// レーベンシュタイン距離
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    fn schema() -> DFSchema {
+        let schema = Schema::new(vec![
+            Field::new("rank", DataType::Int64, true),
+            Field::new("lemma", DataType::Utf8, true),
+            Field::new("PoS", DataType::Utf8, true),
+            Field::new("freq", DataType::Int64, true),
+            Field::new("acad", DataType::Int64, true),
+            Field::new("spok", DataType::Int64, true),
+            Field::new("#texts", DataType::Int64, true),
+        ]);
+        DFSchema::try_from(schema).unwrap()
+    }
+
+    fn col(name: &str) -> Expr {
+        logical_expr::col(format!(r#""{name}""#))
+    }
+
+    fn parse(source: &str) -> Expr {
+        parse_filter(source, &schema()).unwrap()
+    }
+
+    fn parse_error(source: &str) -> String {
+        parse_filter(source, &schema()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("freq > 5000 or rank < 10 and acad > spok"),
+            col("freq").gt(logical_expr::lit(5000_i64)).or(col("rank")
+                .lt(logical_expr::lit(10_i64))
+                .and(col("acad").gt(col("spok"))))
+        );
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!(
+            parse("not freq > 1 and rank < 2"),
+            logical_expr::not(col("freq").gt(logical_expr::lit(1_i64)))
+                .and(col("rank").lt(logical_expr::lit(2_i64)))
+        );
+    }
+
+    #[test]
+    fn parentheses_and_arithmetic_precedence() {
+        assert_eq!(
+            parse("(freq - acad * 2) / 2 >= 1.5"),
+            ((col("freq") - col("acad") * logical_expr::lit(2_i64)) / logical_expr::lit(2_i64))
+                .gt_eq(logical_expr::lit(1.5))
+        );
+    }
+
+    #[test]
+    fn in_list_treats_bare_identifiers_as_strings() {
+        assert_eq!(
+            parse("PoS in (n, 'j')"),
+            logical_expr::in_list(
+                col("PoS"),
+                vec![logical_expr::lit("n"), logical_expr::lit("j")],
+                false
+            )
+        );
+        assert_eq!(
+            parse("PoS not in (v)"),
+            logical_expr::in_list(col("PoS"), vec![logical_expr::lit("v")], true)
+        );
+    }
+
+    #[test]
+    fn quoted_identifiers_and_strings() {
+        assert_eq!(
+            parse(r#""#texts" > 100"#),
+            col("#texts").gt(logical_expr::lit(100_i64))
+        );
+        assert_eq!(
+            parse("lemma = 'it''s'"),
+            col("lemma").eq(logical_expr::lit("it's"))
+        );
+        // キーワードと同じ名前でもクォートすればカラムになる
+        assert!(parse_error(r#""and" = 1"#).contains("unknown column `and`"));
+        assert!(parse_error("lemma = 'open").contains("unterminated quotation"));
+    }
+
+    #[test]
+    fn column_names_are_case_insensitive_if_unique() {
+        assert_eq!(parse("pos = 'n'"), col("PoS").eq(logical_expr::lit("n")));
+    }
+
+    #[test]
+    fn unknown_column_suggests_the_nearest_column() {
+        let error = parse_error("frq > 1");
+        assert!(error.contains("unknown column `frq`. Did you mean `freq`?"));
+        assert!(error.contains("at position 1"));
+
+        let error = parse_error("zzzzzz > 1");
+        assert!(error.contains("Available columns: rank, lemma, PoS, freq, acad, spok, #texts"));
+    }
+
+    #[test]
+    fn trailing_tokens_are_rejected() {
+        assert!(parse_error("freq > 1 rank").contains("unexpected token after the end"));
+    }
+}
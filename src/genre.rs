@@ -0,0 +1,66 @@
+use crate::CustomError;
+
+/// COCAのジャンル(レジスター)．lemmasシートのジャンル別頻度のカラムに対応する．
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Genre {
+    Blog,
+    Web,
+    Tvm,
+    Spok,
+    Fic,
+    Mag,
+    News,
+    Acad,
+}
+
+impl Genre {
+    /// 全てのジャンル(シートのカラム順)
+    pub fn all() -> [Genre; 8] {
+        [
+            Self::Blog,
+            Self::Web,
+            Self::Tvm,
+            Self::Spok,
+            Self::Fic,
+            Self::Mag,
+            Self::News,
+            Self::Acad,
+        ]
+    }
+    pub fn column_name(&self) -> String {
+        match self {
+            Self::Blog => "blog",
+            Self::Web => "web",
+            Self::Tvm => "TVM",
+            Self::Spok => "spok",
+            Self::Fic => "fic",
+            Self::Mag => "mag",
+            Self::News => "news",
+            Self::Acad => "acad",
+        }
+        .to_string()
+    }
+}
+
+impl TryFrom<&str> for Genre {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "blog" | "blogs" => Ok(Self::Blog),
+            "web" => Ok(Self::Web),
+            "tvm" | "tv" | "movies" | "tv/movies" => Ok(Self::Tvm),
+            "spok" | "spoken" => Ok(Self::Spok),
+            "fic" | "fiction" => Ok(Self::Fic),
+            "mag" | "magazine" => Ok(Self::Mag),
+            "news" | "newspaper" => Ok(Self::News),
+            "acad" | "academic" => Ok(Self::Acad),
+            _ => Err(CustomError::msg(format!(
+                "Invalid genre `{value}`. Choose genre in {:?}",
+                Self::all()
+                    .iter()
+                    .map(|genre| genre.column_name())
+                    .collect::<Vec<_>>()
+            ))),
+        }
+    }
+}
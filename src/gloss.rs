@@ -0,0 +1,530 @@
+use crate::batch::string_column;
+use crate::document::{attribute, decode_entities, html_tokens, tag_name, HtmlToken};
+use crate::{CustomError, Error, SheetType};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::SessionContext;
+
+/// 訳語を登録するテーブル名
+pub const GLOSS_TABLE_NAME: &str = "glosses";
+
+/// 訳語をつなぐ区切り
+const GLOSS_SEPARATOR: &str = " / ";
+
+/// JMdictで1つの見出し語と品詞に付ける訳語の最大数
+const MAX_JMDICT_GLOSSES: usize = 5;
+
+/// 辞書ファイルの形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlossFormat {
+    /// EJDict形式のタブ区切り(見出し語，訳語)．訳語の《名》などで品詞を区別する．
+    Ejdict,
+    /// JMdictのXML．英語の訳語から日本語の見出し語を引く．
+    Jmdict,
+    /// 見出し語と訳語，または見出し語と品詞と訳語のcsv
+    Csv,
+}
+
+impl GlossFormat {
+    /// 拡張子から形式を決める
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "txt" | "tsv" => Some(Self::Ejdict),
+            "xml" => Some(Self::Jmdict),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for GlossFormat {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "ejdict" | "tsv" => Ok(Self::Ejdict),
+            "jmdict" | "xml" => Ok(Self::Jmdict),
+            "csv" => Ok(Self::Csv),
+            _ => Err(CustomError::msg(
+                "Invalid dictionary format. Choose format in [\"ejdict\", \"jmdict\", \"csv\"]",
+            )),
+        }
+    }
+}
+
+/// EJDictの品詞の記号をCOCAの品詞に対応させる
+fn ejdict_pos(marker: &str) -> Option<&'static str> {
+    let pos = match marker.chars().next()? {
+        '名' => "n",
+        '動' | '自' | '他' => "v",
+        '助' if marker.starts_with("助動") => "v",
+        '形' => "j",
+        '副' => "r",
+        '前' => "i",
+        '接' => "c",
+        '代' => "p",
+        '冠' => "a",
+        '間' => "u",
+        '数' => "m",
+        _ => return None,
+    };
+    Some(pos)
+}
+
+/// JMdictの品詞(実体参照の名前か展開された説明)をCOCAの品詞に対応させる．
+///
+/// `vs`などのサ変名詞の記号は英語の動詞に当たらないので対応させない．
+/// 動詞の訳語は`to`で始まるので，その場合は`jmdict_headword`で動詞とする．
+fn jmdict_pos(pos: &str) -> Option<&'static str> {
+    let pos = pos.trim().trim_start_matches('&').trim_end_matches(';');
+    let description = pos.to_lowercase();
+    if pos.starts_with("vs") || description.contains("suru") {
+        return None;
+    }
+    let pos = if pos.starts_with("adj") || description.starts_with("adjective") {
+        "j"
+    } else if pos.starts_with("adv") || description.starts_with("adverb") {
+        "r"
+    } else if (pos.starts_with('n') && !pos.starts_with("num")) || description.starts_with("noun") {
+        "n"
+    } else if pos.starts_with('v') || description.contains("verb") {
+        "v"
+    } else if pos == "pn" || description.starts_with("pronoun") {
+        "p"
+    } else if pos == "conj" || description.starts_with("conjunction") {
+        "c"
+    } else if pos == "int" || description.starts_with("interjection") {
+        "u"
+    } else if pos == "num" || description.starts_with("numeric") {
+        "m"
+    } else {
+        return None;
+    };
+    Some(pos)
+}
+
+/// JMdictの英語の訳語を見出し語にする．1語にならない訳語はNone．
+///
+/// 括弧の補足を除き，動詞の`to`や冠詞を取り除く．`to`で始まる訳語は動詞とする．
+fn jmdict_headword(
+    gloss: &str,
+    pos: Option<&'static str>,
+) -> Option<(String, Option<&'static str>)> {
+    let mut text = String::new();
+    let mut depth = 0;
+    for c in gloss.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ if depth == 0 => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text.trim().to_lowercase();
+    let (text, pos) = match text.strip_prefix("to ") {
+        Some(verb) => (verb, Some("v")),
+        None => (
+            ["a ", "an ", "the "]
+                .into_iter()
+                .find_map(|article| text.strip_prefix(article))
+                .unwrap_or(&text),
+            pos,
+        ),
+    };
+    let headword = text.trim();
+    if headword.is_empty()
+        || !headword
+            .chars()
+            .all(|c| c.is_alphabetic() || c == '-' || c == '\'')
+    {
+        return None;
+    }
+    Some((headword.to_string(), pos))
+}
+
+/// 見出し語と品詞から訳語を引く辞書．品詞の無い訳語は全ての品詞に一致する．
+#[derive(Debug, Clone, Default)]
+pub struct Glossary {
+    entries: HashMap<(String, String), Vec<String>>,
+}
+
+impl Glossary {
+    fn insert(&mut self, headword: &str, pos: Option<&str>, gloss: &str) {
+        let headword = headword.trim().to_lowercase();
+        let gloss = gloss.trim();
+        if headword.is_empty() || gloss.is_empty() {
+            return;
+        }
+        let glosses = self
+            .entries
+            .entry((headword, pos.unwrap_or_default().to_string()))
+            .or_default();
+        if !glosses.iter().any(|current| current == gloss) {
+            glosses.push(gloss.to_string());
+        }
+    }
+
+    /// 見出し語の数(品詞ごと)
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// EJDict形式．訳語は` / `で区切られ，《名》などの品詞の記号は次の記号まで続く．
+    /// 見出し語の`colour,color`のような異綴りはそれぞれに登録する．
+    fn parse_ejdict(&mut self, source: &str) {
+        for line in source.lines() {
+            let Some((headwords, meaning)) = line.split_once('\t') else {
+                continue;
+            };
+            let mut pos = None;
+            let mut senses: Vec<(Option<&str>, String)> = Vec::new();
+            for sense in meaning.split('/') {
+                let mut sense = sense.trim();
+                if let Some(marker) = sense.strip_prefix('《') {
+                    if let Some((marker, rest)) = marker.split_once('》') {
+                        if let Some(marker_pos) = ejdict_pos(marker) {
+                            pos = Some(marker_pos);
+                            sense = rest.trim();
+                        }
+                    }
+                }
+                senses.push((pos, sense.to_string()));
+            }
+            for headword in headwords.split(',') {
+                for (pos, sense) in senses.iter() {
+                    self.insert(headword, *pos, sense);
+                }
+            }
+        }
+    }
+
+    /// JMdictのXML．英語の訳語を見出し語として，漢字表記(無ければ読み)を訳語とする．
+    ///
+    /// 品詞は語義(`sense`)の最初に対応の付く`pos`とし，`pos`の無い語義は直前の語義の品詞を
+    /// 引き継ぐ．英語以外の訳語や説明の訳語は使わない．
+    /// 優先度(`ke_pri`，`re_pri`)の付いた語を先に並べ，見出し語と品詞ごとに
+    /// 最大5語とする．
+    fn parse_jmdict(&mut self, source: &str) {
+        // (見出し語，品詞) -> (優先度の有無，日本語)
+        let mut candidates: HashMap<(String, Option<&str>), Vec<(bool, String)>> = HashMap::new();
+        let mut order = Vec::new();
+
+        let mut keb: Option<String> = None;
+        let mut reb: Option<String> = None;
+        let mut priority = false;
+        let mut pos: Option<&str> = None;
+        // 語義の中で既にposを読んだか
+        let mut sense_has_pos = false;
+        let mut current: Option<String> = None;
+        let mut text = String::new();
+        let mut english = true;
+
+        for token in html_tokens(source) {
+            match token {
+                HtmlToken::Text(value) => text.push_str(value),
+                HtmlToken::Tag(tag) => {
+                    let (name, closing) = tag_name(tag);
+                    if !closing {
+                        text.clear();
+                        if name == "entry" {
+                            keb = None;
+                            reb = None;
+                            priority = false;
+                            pos = None;
+                        } else if name == "sense" {
+                            sense_has_pos = false;
+                        } else if name == "gloss" {
+                            english = attribute(tag, "xml:lang").map_or(true, |lang| lang == "eng")
+                                && attribute(tag, "g_type").is_none();
+                        }
+                        current = Some(name);
+                        continue;
+                    }
+                    if current.as_deref() != Some(name.as_str()) {
+                        continue;
+                    }
+                    let value = decode_entities(text.trim());
+                    match name.as_str() {
+                        "keb" if keb.is_none() => keb = Some(value),
+                        "reb" if reb.is_none() => reb = Some(value),
+                        "ke_pri" | "re_pri" => priority = true,
+                        "pos" => {
+                            if !sense_has_pos {
+                                sense_has_pos = true;
+                                pos = None;
+                            }
+                            pos = pos.or_else(|| jmdict_pos(&value));
+                        }
+                        "gloss" if english => {
+                            let Some(japanese) = keb.clone().or_else(|| reb.clone()) else {
+                                continue;
+                            };
+                            if let Some((headword, pos)) = jmdict_headword(&value, pos) {
+                                let key = (headword, pos);
+                                let entry = candidates.entry(key.clone()).or_insert_with(|| {
+                                    order.push(key);
+                                    Vec::new()
+                                });
+                                if !entry.iter().any(|(_, current)| *current == japanese) {
+                                    entry.push((priority, japanese));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                    current = None;
+                }
+            }
+        }
+
+        for key in order {
+            let mut entry = candidates.remove(&key).unwrap_or_default();
+            entry.sort_by_key(|(priority, _)| !priority);
+            let (headword, pos) = key;
+            for (_, japanese) in entry.into_iter().take(MAX_JMDICT_GLOSSES) {
+                self.insert(&headword, pos, &japanese);
+            }
+        }
+    }
+
+    /// csv形式．2列なら見出し語と訳語，3列以上なら見出し語と品詞と訳語とする．
+    /// 1行目が`lemma`か`headword`で始まる場合は見出しとして読み飛ばす．
+    fn parse_csv(&mut self, source: &str) -> Result<(), CustomError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(source.as_bytes());
+        for (line, record) in reader.records().enumerate() {
+            let record = record.map_err(|e| CustomError::new("Invalid csv record.", e))?;
+            let headword = record.get(0).unwrap_or_default();
+            if line == 0 && ["lemma", "headword"].contains(&headword.trim()) {
+                continue;
+            }
+            match record.len() {
+                0 | 1 => {}
+                2 => self.insert(headword, None, record.get(1).unwrap_or_default()),
+                _ => {
+                    let pos = record.get(1).unwrap_or_default().trim();
+                    let pos = (!pos.is_empty()).then_some(pos);
+                    self.insert(headword, pos, record.get(2).unwrap_or_default());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn parse(source: &str, format: GlossFormat) -> Result<Self, CustomError> {
+        let mut glossary = Self::default();
+        match format {
+            GlossFormat::Ejdict => glossary.parse_ejdict(source),
+            GlossFormat::Jmdict => glossary.parse_jmdict(source),
+            GlossFormat::Csv => glossary.parse_csv(source)?,
+        }
+        Ok(glossary)
+    }
+
+    /// 辞書ファイルを読み込む．形式の指定が無ければ拡張子から決める．
+    pub fn read(path: &Path, format: Option<GlossFormat>) -> Result<Self, Error> {
+        let format = match format.or_else(|| GlossFormat::from_path(path)) {
+            Some(format) => format,
+            None => Err(Error::ArgError(
+                CustomError::msg(format!(
+                    "Unknown dictionary format. Use .txt/.tsv (EJDict), .xml (JMdict) or .csv files. path: {}",
+                    path.display()
+                ))
+                .into(),
+            ))?,
+        };
+        Self::parse(&std::fs::read_to_string(path)?, format).map_err(|e| {
+            Error::IOError(
+                CustomError::new(
+                    format!("Failed to read the dictionary. path: {}", path.display()),
+                    e,
+                )
+                .into(),
+            )
+        })
+    }
+
+    /// レンマと品詞の訳語．品詞の一致する訳語が無ければ品詞の無い訳語を用いる．
+    ///
+    /// 例えばEJDictの`saw`は品詞の無い「seeの過去形」と《名》「のこぎり」を持つので，
+    /// `saw (n)`には「のこぎり」が付く．
+    pub fn lookup(&self, lemma: &str, pos: &str) -> Option<String> {
+        let lemma = lemma.trim().to_lowercase();
+        self.entries
+            .get(&(lemma.clone(), pos.to_string()))
+            .or_else(|| self.entries.get(&(lemma, String::new())))
+            .map(|glosses| glosses.join(GLOSS_SEPARATOR))
+    }
+
+    /// lemmasシートのレンマと品詞ごとに訳語を引き，`glosses(lemma, PoS, gloss)`テーブルとして
+    /// 登録する．lemmasシートを登録しておく必要がある．
+    pub async fn register(&self, ctx: &SessionContext) -> Result<(), Error> {
+        let sql = format!(
+            r#"SELECT DISTINCT lemma, "PoS" FROM {}"#,
+            SheetType::First.table_name()
+        );
+        let batches = ctx.sql(&sql).await?.collect().await?;
+        let lemmas = string_column(&batches, "lemma")?;
+        let pos_list = string_column(&batches, "PoS")?;
+
+        let rows = lemmas
+            .into_iter()
+            .zip(pos_list)
+            .filter_map(|(lemma, pos)| {
+                let lemma = lemma?;
+                let pos = pos.unwrap_or_default();
+                let gloss = self.lookup(&lemma, &pos)?;
+                Some((lemma, pos, gloss))
+            })
+            .collect::<Vec<_>>();
+
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "lemma",
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|(lemma, _, _)| lemma.clone()),
+                )) as ArrayRef,
+            ),
+            (
+                "PoS",
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|(_, pos, _)| pos.clone()),
+                )) as ArrayRef,
+            ),
+            (
+                "gloss",
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|(_, _, gloss)| gloss.clone()),
+                )) as ArrayRef,
+            ),
+        ])
+        .map_err(|e| Error::DataFrameError(e.into()))?;
+
+        ctx.register_batch(GLOSS_TABLE_NAME, batch)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ejdict_markers_carry_over_to_later_senses() {
+        let source = "saw\tseeの過去形 / 《名》のこぎり / 《動》…をのこぎりで切る\n\
+                      colour,color\t《名》色 / 色彩 / 《他》…に色を塗る\n";
+        let glossary = Glossary::parse(source, GlossFormat::Ejdict).unwrap();
+
+        assert_eq!(glossary.lookup("saw", "n").as_deref(), Some("のこぎり"));
+        assert_eq!(
+            glossary.lookup("saw", "v").as_deref(),
+            Some("…をのこぎりで切る")
+        );
+        // 《名》の後の記号の無い語義も名詞とする
+        assert_eq!(glossary.lookup("colour", "n").as_deref(), Some("色 / 色彩"));
+        // 異綴りはそれぞれに登録する
+        assert_eq!(
+            glossary.lookup("color", "v").as_deref(),
+            Some("…に色を塗る")
+        );
+        assert_eq!(glossary.len(), 7);
+    }
+
+    #[test]
+    fn lookup_falls_back_to_entries_without_pos() {
+        let source = "saw\tseeの過去形 / 《名》のこぎり\n";
+        let glossary = Glossary::parse(source, GlossFormat::Ejdict).unwrap();
+
+        // 品詞の一致する訳語を優先し，無ければ品詞の無い訳語を使う
+        assert_eq!(glossary.lookup("Saw", "n").as_deref(), Some("のこぎり"));
+        assert_eq!(glossary.lookup("saw", "v").as_deref(), Some("seeの過去形"));
+        assert_eq!(glossary.lookup("see", "v"), None);
+    }
+
+    #[test]
+    fn jmdict_maps_pos_per_sense() {
+        let source = r#"<JMdict>
+<entry>
+<k_ele><keb>勉強</keb><ke_pri>ichi1</ke_pri></k_ele>
+<r_ele><reb>べんきょう</reb></r_ele>
+<sense><pos>&n;</pos><pos>&vs;</pos><gloss>study</gloss><gloss>diligence (in work)</gloss><gloss xml:lang="ger">Studium</gloss></sense>
+<sense><gloss>to study</gloss><gloss>the lesson</gloss></sense>
+</entry>
+<entry>
+<k_ele><keb>散歩</keb></k_ele>
+<sense><pos>&vs;</pos><gloss>walk</gloss><gloss>to take a walk</gloss></sense>
+</entry>
+<entry>
+<r_ele><reb>ダッシュ</reb></r_ele>
+<sense><pos>noun (common) (futsuumeishi)</pos><gloss>a sprint</gloss><gloss g_type="expl">short run</gloss></sense>
+</entry>
+</JMdict>"#;
+        let glossary = Glossary::parse(source, GlossFormat::Jmdict).unwrap();
+
+        // サ変名詞の記号は動詞にせず，語義の最初の品詞(名詞)を使う
+        assert_eq!(glossary.lookup("study", "n").as_deref(), Some("勉強"));
+        assert_eq!(glossary.lookup("diligence", "n").as_deref(), Some("勉強"));
+        // `to`で始まる訳語は動詞とし，品詞の無い語義は直前の語義の品詞を引き継ぐ
+        assert!(glossary
+            .entries
+            .contains_key(&("study".to_string(), "v".to_string())));
+        assert_eq!(glossary.lookup("lesson", "n").as_deref(), Some("勉強"));
+        // `vs`だけの語義は品詞を付けない．1語にならない訳語は使わない
+        assert!(glossary
+            .entries
+            .contains_key(&("walk".to_string(), String::new())));
+        assert!(!glossary
+            .entries
+            .contains_key(&("walk".to_string(), "v".to_string())));
+        assert_eq!(glossary.lookup("walk", "n").as_deref(), Some("散歩"));
+        // 漢字表記が無ければ読みを使い，英語以外や説明の訳語は使わない
+        assert_eq!(glossary.lookup("sprint", "n").as_deref(), Some("ダッシュ"));
+        assert_eq!(glossary.lookup("studium", "n"), None);
+        assert_eq!(glossary.lookup("short", "j"), None);
+    }
+
+    #[test]
+    fn jmdict_headword_strips_parentheses_and_articles() {
+        assert_eq!(
+            jmdict_headword("to run (e.g. a race)", Some("n")),
+            Some(("run".to_string(), Some("v")))
+        );
+        assert_eq!(
+            jmdict_headword("(the) Internet", None),
+            Some(("internet".to_string(), None))
+        );
+        assert_eq!(
+            jmdict_headword("an apple", Some("n")),
+            Some(("apple".to_string(), Some("n")))
+        );
+        assert_eq!(jmdict_headword("to take a walk", None), None);
+        assert_eq!(jmdict_headword("(nuance)", None), None);
+    }
+
+    #[test]
+    fn csv_with_two_or_three_columns() {
+        let source = "lemma,gloss\nsaw,のこぎり\n";
+        let glossary = Glossary::parse(source, GlossFormat::Csv).unwrap();
+        assert_eq!(glossary.lookup("saw", "v").as_deref(), Some("のこぎり"));
+        assert_eq!(glossary.lookup("lemma", ""), None);
+        assert_eq!(glossary.len(), 1);
+
+        let source = "lemma,PoS,gloss\nsaw,n,のこぎり\nsaw,,seeの過去形\n";
+        let glossary = Glossary::parse(source, GlossFormat::Csv).unwrap();
+        assert_eq!(glossary.lookup("saw", "n").as_deref(), Some("のこぎり"));
+        assert_eq!(glossary.lookup("saw", "v").as_deref(), Some("seeの過去形"));
+        assert_eq!(glossary.len(), 2);
+    }
+}
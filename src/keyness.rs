@@ -0,0 +1,148 @@
+use crate::{CustomError, Error, Genre, SheetType};
+
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// キーネス分析の参照コーパス
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    /// 単一のジャンル
+    Genre(Genre),
+    /// 対象以外の全てのジャンル
+    Rest,
+}
+
+impl TryFrom<&str> for Reference {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "rest" | "others" => Ok(Self::Rest),
+            _ => Ok(Self::Genre(Genre::try_from(value)?)),
+        }
+    }
+}
+
+/// 有意水準に対応する対数尤度比(自由度1)の臨界値
+pub fn critical_value(significance: f64) -> Result<f64, CustomError> {
+    [(0.05, 3.84), (0.01, 6.63), (0.001, 10.83), (0.0001, 15.13)]
+        .into_iter()
+        .find(|(p, _)| (p - significance).abs() < f64::EPSILON)
+        .map(|(_, critical)| critical)
+        .ok_or_else(|| {
+            CustomError::msg(
+                "Invalid significance level. Choose significance in [0.05, 0.01, 0.001, 0.0001]",
+            )
+        })
+}
+
+/// ジャンルのカラムをNULLを0として参照するSQLの式
+pub(crate) fn genre_sql(genre: Genre) -> String {
+    format!(
+        r#"COALESCE(CAST("{}" AS DOUBLE), 0.0)"#,
+        genre.column_name()
+    )
+}
+
+/// lemmasシートに対してジャンル間のキーネスを求める．
+///
+/// 各レンマについて対象(target)と参照(reference)の頻度から対数尤度比(log_likelihood)，
+/// %DIFF(percent_diff)，Log Ratio(log_ratio)を計算する．コーパスサイズは各ジャンルの
+/// カラムの合計とする．`negative`がfalseなら対象で多く使われる語を，trueなら少ない語を
+/// 対数尤度比の降順で返す．
+pub async fn keyness(
+    ctx: &SessionContext,
+    target: Genre,
+    reference: Reference,
+    significance: Option<f64>,
+    min_log_ratio: Option<f64>,
+    negative: bool,
+    pos_list: Option<&[String]>,
+) -> Result<DataFrame, Error> {
+    let reference_sql = match reference {
+        Reference::Genre(genre) => {
+            if genre == target {
+                Err(Error::ArgError(
+                    CustomError::msg("The target and the reference must be different genres.")
+                        .into(),
+                ))?;
+            }
+            genre_sql(genre)
+        }
+        Reference::Rest => Genre::all()
+            .into_iter()
+            .filter(|genre| *genre != target)
+            .map(genre_sql)
+            .collect::<Vec<_>>()
+            .join(" + "),
+    };
+
+    let mut conditions = vec![if negative {
+        "a / c < b / d".to_string()
+    } else {
+        "a / c > b / d".to_string()
+    }];
+    if let Some(significance) = significance {
+        let critical = critical_value(significance).map_err(|e| Error::ArgError(e.into()))?;
+        conditions.push(format!("log_likelihood >= {critical}"));
+    }
+    if let Some(min_log_ratio) = min_log_ratio {
+        conditions.push(format!("ABS(log_ratio) >= {min_log_ratio}"));
+    }
+    if let Some(pos_list) = pos_list {
+        conditions.push(format!(
+            r#""PoS" IN ({})"#,
+            pos_list
+                .iter()
+                .map(|pos| format!("'{}'", pos.replace('\'', "''")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    let sql = format!(
+        r#"WITH counts AS (
+            SELECT "rank", lemma, "PoS", {target} AS a, {reference_sql} AS b
+            FROM {table}
+        ), totals AS (
+            SELECT SUM(a) AS c, SUM(b) AS d FROM counts
+        ), expected AS (
+            SELECT counts.*, c, d, c * (a + b) / (c + d) AS e1, d * (a + b) / (c + d) AS e2
+            FROM counts CROSS JOIN totals
+            WHERE a + b > 0
+        ), scores AS (
+            SELECT *,
+                2.0 * (
+                    CASE WHEN a > 0 THEN a * LN(a / e1) ELSE 0.0 END
+                    + CASE WHEN b > 0 THEN b * LN(b / e2) ELSE 0.0 END
+                ) AS log_likelihood,
+                CASE WHEN b > 0 THEN (a / c - b / d) * 100.0 / (b / d) END AS percent_diff,
+                LOG2(
+                    (CASE WHEN a > 0 THEN a ELSE 0.5 END / c)
+                    / (CASE WHEN b > 0 THEN b ELSE 0.5 END / d)
+                ) AS log_ratio
+            FROM expected
+        )
+        SELECT "rank", lemma, "PoS",
+            a AS target_freq,
+            b AS reference_freq,
+            a * 1000000.0 / c AS target_per_mil,
+            b * 1000000.0 / d AS reference_per_mil,
+            log_likelihood,
+            percent_diff,
+            log_ratio,
+            CASE
+                WHEN log_likelihood >= 15.13 THEN 'p<0.0001'
+                WHEN log_likelihood >= 10.83 THEN 'p<0.001'
+                WHEN log_likelihood >= 6.63 THEN 'p<0.01'
+                WHEN log_likelihood >= 3.84 THEN 'p<0.05'
+                ELSE 'n.s.'
+            END AS significance
+        FROM scores
+        WHERE {conditions}
+        ORDER BY log_likelihood DESC, "rank" ASC"#,
+        target = genre_sql(target),
+        table = SheetType::First.table_name(),
+        conditions = conditions.join(" AND "),
+    );
+
+    Ok(ctx.sql(&sql).await?)
+}
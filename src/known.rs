@@ -0,0 +1,296 @@
+use crate::batch::string_column;
+use crate::distribution::LemmaEntry;
+use crate::{CustomError, Error};
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::SessionContext;
+
+/// 既知語のストアのデフォルトのパス
+pub const DEFAULT_KNOWN_PATH: &str = "./known.csv";
+
+/// 既知語を登録するテーブル名
+pub const KNOWN_TABLE_NAME: &str = "known";
+
+/// 既知のレンマのリスト．品詞の無いレンマは全ての品詞に一致する．
+///
+/// テキストのリストは1行に1語で，`lemma`または`lemma,PoS`(タブ区切りも可)の形式．
+/// ファイルのストアは`lemma,PoS`のヘッダーを持つcsvで，品詞の無い行は`PoS`を空とする．
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KnownList {
+    /// 品詞を問わないレンマ
+    lemmas: BTreeSet<String>,
+    /// 品詞を指定したレンマ
+    lemma_pos: BTreeSet<(String, String)>,
+}
+
+impl KnownList {
+    pub fn parse(source: &str) -> Self {
+        let mut known = Self::default();
+        for line in source.lines() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split([',', '\t']).map(|field| field.trim());
+            let lemma = fields.next().unwrap_or_default();
+            known.insert(lemma, fields.next());
+        }
+        known
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        if !path.is_file() {
+            Err(Error::IOError(
+                CustomError::msg(format!(
+                    "The known list cannot be found. path: {}",
+                    path.display()
+                ))
+                .into(),
+            ))?;
+        }
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// ストアを読み込む．ファイルが無い場合は空とする．
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut known = Self::default();
+        if !path.is_file() {
+            return Ok(known);
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_path(path)
+            .map_err(|e| {
+                Error::IOError(
+                    CustomError::new(
+                        format!("Failed to read the known words. path: {}", path.display()),
+                        e,
+                    )
+                    .into(),
+                )
+            })?;
+        for record in reader.records() {
+            let record = record.map_err(|e| {
+                Error::IOError(
+                    CustomError::new(
+                        format!("Failed to read the known words. path: {}", path.display()),
+                        e,
+                    )
+                    .into(),
+                )
+            })?;
+            known.insert(record.get(0).unwrap_or_default(), record.get(1));
+        }
+        Ok(known)
+    }
+
+    /// ストアに書き込む
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let to_error = |e: csv::Error| {
+            Error::IOError(
+                CustomError::new(
+                    format!("Failed to write the known words. path: {}", path.display()),
+                    e,
+                )
+                .into(),
+            )
+        };
+        let mut writer = csv::Writer::from_path(path).map_err(to_error)?;
+        writer.write_record(["lemma", "PoS"]).map_err(to_error)?;
+        for (lemma, pos) in self.entries() {
+            writer
+                .write_record([lemma.as_str(), pos.as_deref().unwrap_or_default()])
+                .map_err(to_error)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// レンマを加える．新たに加わればtrue．
+    pub fn insert(&mut self, lemma: &str, pos: Option<&str>) -> bool {
+        let lemma = lemma.trim().to_lowercase();
+        if lemma.is_empty() {
+            return false;
+        }
+        match pos.map(str::trim).filter(|pos| !pos.is_empty()) {
+            Some(pos) => self.lemma_pos.insert((lemma, pos.to_string())),
+            None => self.lemmas.insert(lemma),
+        }
+    }
+
+    /// レンマを除く．除かれればtrue．品詞を指定しなければ全ての品詞の行を除く．
+    pub fn remove(&mut self, lemma: &str, pos: Option<&str>) -> bool {
+        let lemma = lemma.trim().to_lowercase();
+        match pos.map(str::trim).filter(|pos| !pos.is_empty()) {
+            Some(pos) => self.lemma_pos.remove(&(lemma, pos.to_string())),
+            None => {
+                let before = self.len();
+                self.lemmas.remove(&lemma);
+                self.lemma_pos.retain(|(known, _)| *known != lemma);
+                before != self.len()
+            }
+        }
+    }
+
+    /// 他のリストを加え，新たに加わった数を返す
+    pub fn extend(&mut self, other: &KnownList) -> usize {
+        other
+            .entries()
+            .into_iter()
+            .filter(|(lemma, pos)| self.insert(lemma, pos.as_deref()))
+            .count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lemmas.len() + self.lemma_pos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// レンマと品詞の組のリスト
+    pub fn entries(&self) -> Vec<(String, Option<String>)> {
+        let mut entries = self
+            .lemmas
+            .iter()
+            .map(|lemma| (lemma.clone(), None))
+            .chain(
+                self.lemma_pos
+                    .iter()
+                    .map(|(lemma, pos)| (lemma.clone(), Some(pos.clone()))),
+            )
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    pub fn contains(&self, lemma: &LemmaEntry) -> bool {
+        self.lemmas.contains(&lemma.lemma.to_lowercase())
+            || self
+                .lemma_pos
+                .contains(&(lemma.lemma.to_lowercase(), lemma.pos.clone()))
+    }
+
+    /// 結果の`lemma`と`PoS`のカラムのレンマを加え，新たに加わった数を返す
+    pub fn mark(&mut self, batches: &[RecordBatch]) -> Result<usize, Error> {
+        let Some(schema) = batches.first().map(|batch| batch.schema()) else {
+            return Ok(0);
+        };
+        for column in ["lemma", "PoS"] {
+            if schema.column_with_name(column).is_none() {
+                Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "The query result has no {column} column for marking known words."
+                    ))
+                    .into(),
+                ))?;
+            }
+        }
+
+        let lemmas = string_column(batches, "lemma")?;
+        let pos_list = string_column(batches, "PoS")?;
+        Ok(lemmas
+            .into_iter()
+            .zip(pos_list)
+            .filter_map(|(lemma, pos)| lemma.map(|lemma| (lemma, pos)))
+            .filter(|(lemma, pos)| self.insert(lemma, pos.as_deref()))
+            .count())
+    }
+
+    /// `known(lemma, PoS)`テーブルとして登録する．品詞の無いレンマのPoSは空文字列とする．
+    /// レンマは小文字なので，シートとは`lower(lemma)`で結合する．
+    pub fn register(&self, ctx: &SessionContext) -> Result<(), Error> {
+        let entries = self.entries();
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "lemma",
+                Arc::new(StringArray::from_iter_values(
+                    entries.iter().map(|(lemma, _)| lemma.clone()),
+                )) as ArrayRef,
+            ),
+            (
+                "PoS",
+                Arc::new(StringArray::from_iter_values(
+                    entries
+                        .iter()
+                        .map(|(_, pos)| pos.clone().unwrap_or_default()),
+                )) as ArrayRef,
+            ),
+        ])
+        .map_err(|e| Error::DataFrameError(e.into()))?;
+
+        ctx.register_batch(KNOWN_TABLE_NAME, batch)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(lemma: &str, pos: &str) -> LemmaEntry {
+        LemmaEntry {
+            rank: 1,
+            lemma: lemma.to_string(),
+            pos: pos.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_lines_with_and_without_pos() {
+        let known = KnownList::parse("\u{feff}# comment\nThe\nrun,v\nEnglish\tj\n\n  light , \n");
+        assert_eq!(
+            known.entries(),
+            vec![
+                ("english".to_string(), Some("j".to_string())),
+                ("light".to_string(), None),
+                ("run".to_string(), Some("v".to_string())),
+                ("the".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_lowercases_lemmas() {
+        let mut known = KnownList::default();
+        assert!(known.insert(" English ", Some("n")));
+        assert!(!known.insert("english", Some(" n ")));
+        assert!(known.insert("english", None));
+        assert!(!known.insert("ENGLISH", Some("")));
+        assert!(!known.insert("  ", None));
+        assert_eq!(known.len(), 2);
+    }
+
+    #[test]
+    fn empty_pos_matches_any_pos() {
+        let mut known = KnownList::default();
+        known.insert("light", None);
+        known.insert("run", Some("v"));
+
+        assert!(known.contains(&entry("light", "n")));
+        assert!(known.contains(&entry("Light", "j")));
+        assert!(known.contains(&entry("run", "v")));
+        assert!(!known.contains(&entry("run", "n")));
+        assert!(!known.contains(&entry("walk", "v")));
+    }
+
+    #[test]
+    fn remove_without_pos_removes_every_pos() {
+        let mut known = KnownList::parse("run\nrun,v\nrun,n\nwalk,v\n");
+        assert!(known.remove("run", Some("n")));
+        assert!(!known.remove("run", Some("n")));
+        assert!(known.remove("RUN", None));
+        assert_eq!(
+            known.entries(),
+            vec![("walk".to_string(), Some("v".to_string()))]
+        );
+    }
+}
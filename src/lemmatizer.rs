@@ -0,0 +1,115 @@
+use crate::batch::{f64_column, string_column};
+use crate::distribution::LemmaEntry;
+use crate::{CustomError, Error, SheetType};
+
+use std::collections::HashMap;
+
+use datafusion::prelude::SessionContext;
+
+/// wordFormsシートでレンマのrankを表すカラムの候補
+const LEMMA_RANK_COLUMNS: [&str; 2] = ["lemRank", "rank"];
+
+/// 語形からレンマを引く辞書
+#[derive(Debug, Clone, Default)]
+pub struct Lemmatizer {
+    forms: HashMap<String, LemmaEntry>,
+}
+
+impl Lemmatizer {
+    /// 登録済みのwordFormsシートとlemmasシートから作る．
+    ///
+    /// 語形が複数のレンマに属する場合は語形の頻度(wordFreq)が最も高いもの，
+    /// 無ければrankの最も小さいものを選ぶ．wordFormsに無い語はlemmasシートの
+    /// レンマそのものとして引けるようにする．
+    pub async fn load(ctx: &SessionContext) -> Result<Self, Error> {
+        let word_forms = SheetType::Third.table_name();
+        let schema = ctx.table(word_forms.as_str()).await?.schema().clone();
+        let has_column = |column: &str| schema.has_column_with_unqualified_name(column);
+
+        let rank_column = LEMMA_RANK_COLUMNS
+            .into_iter()
+            .find(|column| has_column(column))
+            .ok_or_else(|| {
+                Error::DataFrameError(
+                    CustomError::msg("The wordForms sheet has no rank column for lemmas.").into(),
+                )
+            })?;
+        let word_freq = if has_column("wordFreq") {
+            r#""wordFreq""#
+        } else {
+            "NULL"
+        };
+
+        let mut forms: HashMap<String, (LemmaEntry, f64)> = HashMap::new();
+        let mut insert = |word: String, entry: LemmaEntry, freq: f64| {
+            let word = word.trim().to_lowercase();
+            match forms.get(&word) {
+                Some((current, current_freq))
+                    if (*current_freq, -current.rank) >= (freq, -entry.rank) => {}
+                _ => {
+                    forms.insert(word, (entry, freq));
+                }
+            }
+        };
+
+        let sql = format!(
+            r#"SELECT word, lemma, "PoS", "{rank_column}" AS lemma_rank, {word_freq} AS word_freq FROM {word_forms}"#
+        );
+        let batches = ctx.sql(&sql).await?.collect().await?;
+        let words = string_column(&batches, "word")?;
+        let lemmas = string_column(&batches, "lemma")?;
+        let pos_list = string_column(&batches, "PoS")?;
+        let ranks = f64_column(&batches, "lemma_rank")?;
+        let freqs = f64_column(&batches, "word_freq")?;
+        for ((((word, lemma), pos), rank), freq) in words
+            .into_iter()
+            .zip(lemmas)
+            .zip(pos_list)
+            .zip(ranks)
+            .zip(freqs)
+        {
+            let (Some(word), Some(lemma), Some(rank)) = (word, lemma, rank) else {
+                continue;
+            };
+            let entry = LemmaEntry {
+                rank: rank.round() as i64,
+                lemma,
+                pos: pos.unwrap_or_default(),
+            };
+            insert(word, entry, freq.unwrap_or(0.0));
+        }
+
+        // lemmasシートのレンマ自体は語形の頻度が分からないので最も低い優先度とする
+        let sql = format!(
+            r#"SELECT "rank", lemma, "PoS" FROM {}"#,
+            SheetType::First.table_name()
+        );
+        let batches = ctx.sql(&sql).await?.collect().await?;
+        let lemmas = string_column(&batches, "lemma")?;
+        let pos_list = string_column(&batches, "PoS")?;
+        let ranks = f64_column(&batches, "rank")?;
+        for ((lemma, pos), rank) in lemmas.into_iter().zip(pos_list).zip(ranks) {
+            let (Some(lemma), Some(rank)) = (lemma, rank) else {
+                continue;
+            };
+            let entry = LemmaEntry {
+                rank: rank.round() as i64,
+                lemma: lemma.clone(),
+                pos: pos.unwrap_or_default(),
+            };
+            insert(lemma, entry, -1.0);
+        }
+
+        Ok(Self {
+            forms: forms
+                .into_iter()
+                .map(|(word, (entry, _))| (word, entry))
+                .collect(),
+        })
+    }
+
+    /// 小文字の語形に対応するレンマ
+    pub fn lemmatize(&self, word: &str) -> Option<&LemmaEntry> {
+        self.forms.get(word)
+    }
+}
@@ -1,6 +1,87 @@
+mod batch;
+pub mod cluster;
+pub mod compare;
+pub mod config;
+pub mod correlation;
+pub mod dispersion;
+pub mod distribution;
+pub mod document;
 mod error;
+pub mod export;
+pub mod external;
+pub mod extract;
+pub mod filter;
+mod genre;
+pub mod gloss;
+pub mod keyness;
+pub mod known;
+pub mod lemmatizer;
+pub mod profile;
 pub mod query;
+mod random;
+pub mod sample;
+pub mod similarity;
+pub mod sort;
+pub mod stats;
+pub mod subgenre;
+pub mod text;
+pub mod top;
+pub mod views;
+pub mod vocabulary;
+pub mod vst;
 pub use error::{CustomError, Error};
+pub use genre::Genre;
+
+/// 頻度帯(band)の幅．rankの1000語ごとに1つの帯とする．
+pub const BAND_SIZE: usize = 1000;
+
+/// 頻度帯の範囲(両端を含む)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl BandRange {
+    /// rankの属する帯
+    pub fn band_of(rank: i64) -> usize {
+        (rank.max(1) as usize - 1) / BAND_SIZE + 1
+    }
+
+    pub fn contains_rank(&self, rank: i64) -> bool {
+        let band = Self::band_of(rank);
+        self.start <= band && band <= self.end
+    }
+
+    pub fn bands(&self) -> std::ops::RangeInclusive<usize> {
+        self.start..=self.end
+    }
+}
+
+impl TryFrom<&str> for BandRange {
+    type Error = CustomError;
+    /// `3`，`1..5`，`1..=5`の形式を受け付ける
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let parse = |value: &str| {
+            value.trim().parse::<usize>().map_err(|e| {
+                CustomError::new(format!("Invalid band number. value: {}", value.trim()), e)
+            })
+        };
+        let (start, end) = match value.split_once("..") {
+            Some((start, end)) => (parse(start)?, parse(end.trim_start_matches('='))?),
+            None => {
+                let band = parse(value)?;
+                (band, band)
+            }
+        };
+        if start == 0 || start > end {
+            Err(CustomError::msg(format!(
+                "Invalid band range. Bands start from 1 and the start must not exceed the end. value: {value}"
+            )))?;
+        }
+        Ok(Self { start, end })
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum SheetType {
@@ -0,0 +1,95 @@
+use crate::query::sum_over_all_expr;
+use crate::{CustomError, Genre};
+
+use std::collections::BTreeMap;
+
+use datafusion::arrow::datatypes::DataType;
+use datafusion::logical_expr;
+use datafusion::prelude::Expr;
+
+/// ジャンルの重み付けによるレジスタープロファイル．重みの合計は1に正規化する．
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterProfile {
+    weights: Vec<(Genre, f64)>,
+}
+
+impl RegisterProfile {
+    /// `spok=0.6,fic=0.3,news=0.1`の形式の文字列をパースする
+    pub fn parse(source: &str) -> Result<Self, CustomError> {
+        let mut weights = BTreeMap::new();
+        for item in source.split(',') {
+            let (genre, weight) = item.split_once('=').ok_or_else(|| {
+                CustomError::msg(format!(
+                    "Invalid profile item `{item}`. Use the form like `spok=0.6,fic=0.3,news=0.1`."
+                ))
+            })?;
+            let weight = weight.trim().parse::<f64>().map_err(|e| {
+                CustomError::new(format!("Invalid weight for the genre `{genre}`."), e)
+            })?;
+            weights.insert(genre.trim().to_string(), weight);
+        }
+        Self::from_weights(&weights)
+    }
+    /// ジャンル名から重みへの対応から作成する
+    pub fn from_weights(weights: &BTreeMap<String, f64>) -> Result<Self, CustomError> {
+        let mut genre_weights: Vec<(Genre, f64)> = Vec::new();
+        for (genre, weight) in weights.iter() {
+            let genre = Genre::try_from(genre.as_str())?;
+            if !weight.is_finite() || *weight < 0.0 {
+                return Err(CustomError::msg(format!(
+                    "The weight of the genre `{}` must be a non-negative number.",
+                    genre.column_name()
+                )));
+            }
+            match genre_weights.iter_mut().find(|(g, _)| *g == genre) {
+                Some((_, w)) => *w += weight,
+                None => genre_weights.push((genre, *weight)),
+            }
+        }
+
+        let total = genre_weights.iter().map(|(_, weight)| weight).sum::<f64>();
+        if total <= 0.0 {
+            return Err(CustomError::msg(
+                "The total weight of the profile must be positive.",
+            ));
+        }
+
+        genre_weights.retain(|(_, weight)| *weight > 0.0);
+        genre_weights.sort_by_key(|(genre, _)| *genre);
+        for (_, weight) in genre_weights.iter_mut() {
+            *weight /= total;
+        }
+
+        Ok(Self {
+            weights: genre_weights,
+        })
+    }
+    /// 正規化された重み
+    pub fn weights(&self) -> &[(Genre, f64)] {
+        &self.weights
+    }
+    /// 設定ファイルに保存する形式
+    pub fn to_weights(&self) -> BTreeMap<String, f64> {
+        self.weights
+            .iter()
+            .map(|(genre, weight)| (genre.column_name(), *weight))
+            .collect()
+    }
+    /// 重み付きの100万語あたりの頻度を求める式．
+    ///
+    /// 各ジャンルの頻度をシート全体のジャンルの合計で100万語あたりに正規化し，重みを掛けて足し合わせる．
+    pub fn score_expr(&self) -> Expr {
+        self.weights
+            .iter()
+            .map(|(genre, weight)| {
+                let column = logical_expr::col(format!(r#""{}""#, genre.column_name()));
+                let freq = logical_expr::when(column.clone().is_null(), logical_expr::lit(0.0))
+                    .otherwise(logical_expr::cast(column, DataType::Float64))
+                    .unwrap();
+
+                logical_expr::lit(*weight * 1_000_000.0) * freq.clone() / sum_over_all_expr(freq)
+            })
+            .reduce(|acc, expr| acc + expr)
+            .unwrap()
+    }
+}
@@ -1,20 +1,113 @@
-use crate::{Columns, CustomError, Error, MatchType, SheetType};
+use crate::dispersion::{RankMode, DISP_COLUMN};
+use crate::profile::RegisterProfile;
+use crate::sample::Sample;
+use crate::sort::{push_tiebreak, SortKey};
+use crate::top::{top_per_group, Grouping};
+use crate::{Columns, CustomError, Error, MatchType, SheetType, BAND_SIZE};
 
+use datafusion::arrow::datatypes::DataType;
 use datafusion::logical_expr;
+use datafusion::logical_expr::expr::WindowFunction;
+use datafusion::logical_expr::{
+    AggregateFunction, BuiltInWindowFunction, JoinType, WindowFrame, WindowFunctionDefinition,
+};
 use datafusion::prelude::{DataFrame, Expr};
 
+/// rankから頻度帯(1始まり)を求める式
+pub fn band_expr() -> Expr {
+    let rank = logical_expr::cast(logical_expr::col("rank"), DataType::Int64);
+    (rank - logical_expr::lit(1_i64)) / logical_expr::lit(BAND_SIZE as i64)
+        + logical_expr::lit(1_i64)
+}
+
+/// ROW_NUMBER() OVER (PARTITION BY .. ORDER BY ..)の式
+pub fn row_number_expr(partition_by: Vec<Expr>, order_by: Vec<Expr>) -> Expr {
+    let window_frame = WindowFrame::new(!order_by.is_empty());
+    Expr::WindowFunction(WindowFunction::new(
+        WindowFunctionDefinition::BuiltInWindowFunction(BuiltInWindowFunction::RowNumber),
+        vec![],
+        partition_by,
+        order_by,
+        window_frame,
+    ))
+}
+
+/// SUM(..) OVER ()の式．シート全体の合計を各行に付与する．
+pub fn sum_over_all_expr(expr: Expr) -> Expr {
+    Expr::WindowFunction(WindowFunction::new(
+        WindowFunctionDefinition::AggregateFunction(AggregateFunction::Sum),
+        vec![expr],
+        vec![],
+        vec![],
+        WindowFrame::new(false),
+    ))
+}
+
+/// `simple_query`の指定．使わない機能は`Default`のままにする．
+#[derive(Clone, Default)]
+pub struct QueryOptions<'a> {
+    /// 検索する語と一致の方法
+    pub words_and_match: Option<(&'a [String], MatchType)>,
+    /// 絞り込む品詞
+    pub pos_list: Option<&'a [String]>,
+    /// `--where`のフィルター式
+    pub filter: Option<&'a str>,
+    /// 除外する既知語のテーブル(`lemma`と`PoS`)
+    pub known: Option<DataFrame>,
+    /// 無作為抽出
+    pub sample: Option<&'a Sample>,
+    /// 付与する訳語のテーブル(`lemma`, `PoS`, `gloss`)
+    pub glosses: Option<DataFrame>,
+    /// ソートキー
+    pub sort_keys: Option<&'a [SortKey]>,
+    /// グループごとの上位語数とグループ
+    pub top_per: Option<(usize, &'a Grouping)>,
+    /// レジスタープロファイル
+    pub profile: Option<&'a RegisterProfile>,
+    /// 分散度を加味した順位付け
+    pub rank_mode: Option<RankMode>,
+    /// 分散度の下限
+    pub min_disp: Option<f64>,
+    /// フィルター後の順位を付け直すか
+    pub rerank: bool,
+    /// 順位を付け直すときに降順で並べるカラム
+    pub rerank_by: Option<&'a str>,
+    /// 読み飛ばす行数
+    pub skip: Option<usize>,
+    /// 最大の行数
+    pub limit: Option<usize>,
+    /// 既定のカラムに加えて出力するカラム
+    pub additional_columns: Option<&'a [String]>,
+    /// すべてのカラムを出力するか
+    pub all: bool,
+}
+
 /// cliのクエリのベース
 pub fn simple_query(
     mut df: DataFrame,
     sheet_type: SheetType,
-    words_and_match: Option<(&[String], MatchType)>,
-    pos_list: Option<&[String]>,
-    sorted_column: Option<&str>,
-    skip: Option<usize>,
-    limit: Option<usize>,
-    additional_columns: Option<&[String]>,
-    all: bool,
+    options: QueryOptions,
 ) -> Result<DataFrame, Error> {
+    let QueryOptions {
+        words_and_match,
+        pos_list,
+        filter,
+        known,
+        sample,
+        glosses,
+        sort_keys,
+        top_per,
+        profile,
+        rank_mode,
+        min_disp,
+        rerank,
+        rerank_by,
+        skip,
+        limit,
+        additional_columns,
+        all,
+    } = options;
+
     // filter用のカラム
     let mut columns = match sheet_type {
         SheetType::First => crate::columns!["rank", "lemma", "freq"],
@@ -27,6 +120,66 @@ pub fn simple_query(
         columns = Columns::all();
     }
 
+    // profile
+    // フィルターの前に計算し，シート全体での順位とする
+    if let Some(profile) = profile {
+        for (genre, _) in profile.weights().iter() {
+            if !df
+                .schema()
+                .has_column_with_unqualified_name(&genre.column_name())
+            {
+                Err(Error::ArgError(
+                    CustomError::msg("Invalid sheet type for specifying register profile.").into(),
+                ))?;
+            }
+        }
+
+        df = df
+            .with_column("profile_score", profile.score_expr())?
+            .with_column(
+                "profile_rank",
+                row_number_expr(
+                    vec![],
+                    vec![
+                        SortKey::desc("profile_score").to_expr(),
+                        SortKey::asc("rank").to_expr(),
+                    ],
+                ),
+            )?;
+
+        if let Columns::List(list) = &mut columns {
+            list.shift_insert(0, "profile_rank".to_string());
+            list.insert("profile_score".to_string());
+        }
+    }
+
+    // rank_mode
+    // profileと同じくフィルターの前に計算する
+    if let Some(rank_mode) = rank_mode {
+        let score_column = rank_mode.column_name();
+        if let Some(score_expr) = rank_mode.score_expr(df.schema())? {
+            df = df.with_column(&score_column, score_expr)?;
+        }
+        df = df.with_column(
+            "dispersion_rank",
+            row_number_expr(
+                vec![],
+                vec![
+                    SortKey::desc(score_column.clone()).to_expr(),
+                    SortKey::asc("rank").to_expr(),
+                ],
+            ),
+        )?;
+
+        if let Columns::List(list) = &mut columns {
+            list.shift_insert(0, "dispersion_rank".to_string());
+            list.insert(score_column);
+            if df.schema().has_column_with_unqualified_name(DISP_COLUMN) {
+                list.insert(DISP_COLUMN.to_string());
+            }
+        }
+    }
+
     // where句を記述する
     let mut where_expr: Option<Expr> = None;
 
@@ -108,23 +261,212 @@ pub fn simple_query(
         };
     }
 
+    // filter
+    if let Some(filter) = filter {
+        let filter_expr = crate::filter::parse_filter(filter, df.schema())?;
+
+        // where_exprの更新
+        match where_expr {
+            Some(expr) => where_expr = Some(expr.and(filter_expr)),
+            None => {
+                where_expr = Some(filter_expr);
+            }
+        };
+    }
+
+    // min_disp
+    if let Some(min_disp) = min_disp {
+        if !df.schema().has_column_with_unqualified_name(DISP_COLUMN) {
+            Err(Error::ArgError(
+                CustomError::msg("Invalid sheet type for specifying minimum dispersion.").into(),
+            ))?;
+        }
+
+        let disp_expr = logical_expr::cast(logical_expr::col(DISP_COLUMN), DataType::Float64)
+            .gt_eq(logical_expr::lit(min_disp));
+
+        // where_exprの更新
+        match where_expr {
+            Some(expr) => where_expr = Some(expr.and(disp_expr)),
+            None => {
+                where_expr = Some(disp_expr);
+            }
+        };
+
+        columns.insert(DISP_COLUMN.to_string());
+    }
+
     // where句の追加
     if let Some(where_expr) = where_expr {
         df = df.filter(where_expr)?;
     }
 
-    // sorted_column
-    if let Some(sorted_column) = sorted_column {
-        // ソートしたいカラムがあるか確認
-        if !df.schema().has_column_with_unqualified_name(sorted_column) {
-            Err(Error::ArgError(
-                CustomError::msg("Invalid column for sorting in the specified sheet.").into(),
-            ))?;
+    // known
+    // 既知語のテーブルとの反結合で未知のレンマだけを残す
+    if let Some(known) = known {
+        for column in ["lemma", "PoS"] {
+            if !df.schema().has_column_with_unqualified_name(column) {
+                Err(Error::ArgError(
+                    CustomError::msg("Invalid sheet type for excluding known words.").into(),
+                ))?;
+            }
+        }
+
+        let known = known.select(vec![
+            logical_expr::col("lemma").alias("known_lemma"),
+            logical_expr::col(r#""PoS""#).alias("known_pos"),
+        ])?;
+        // ストアのレンマは小文字なので，大文字を含むレンマ(Englishなど)も小文字にして比べる．
+        // 品詞の無い既知語は全ての品詞に一致する
+        let known_pos = logical_expr::col("known_pos");
+        let on_expr = logical_expr::lower(logical_expr::col("lemma"))
+            .eq(logical_expr::col("known_lemma"))
+            .and(
+                known_pos
+                    .clone()
+                    .eq(logical_expr::lit(""))
+                    .or(logical_expr::col(r#""PoS""#).eq(known_pos)),
+            );
+
+        df = df.join_on(known, JoinType::LeftAnti, [on_expr])?;
+    }
+
+    // sample
+    // 絞り込んだ行から抽出し，並べ替えや上位N件はその結果に対して行う
+    if let Some(sample) = sample {
+        df = sample.apply(df)?;
+    }
+
+    // glosses
+    // 訳語のテーブルとレンマと品詞で左外部結合する
+    if let Some(glosses) = glosses {
+        for column in ["lemma", "PoS"] {
+            if !df.schema().has_column_with_unqualified_name(column) {
+                Err(Error::ArgError(
+                    CustomError::msg("Invalid sheet type for attaching glosses.").into(),
+                ))?;
+            }
         }
 
-        columns.insert(sorted_column.to_string());
+        let mut select_exprs = df
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| logical_expr::col(format!(r#""{}""#, field.name())))
+            .collect::<Vec<_>>();
+        select_exprs.push(logical_expr::col("gloss"));
+
+        let glosses = glosses.select(vec![
+            logical_expr::col("lemma").alias("gloss_lemma"),
+            logical_expr::col(r#""PoS""#).alias("gloss_pos"),
+            logical_expr::col("gloss"),
+        ])?;
+        let on_expr = logical_expr::col("lemma")
+            .eq(logical_expr::col("gloss_lemma"))
+            .and(logical_expr::col(r#""PoS""#).eq(logical_expr::col("gloss_pos")));
+
+        df = df
+            .join_on(glosses, JoinType::Left, [on_expr])?
+            .select(select_exprs)?;
+
+        columns.insert("gloss".to_string());
+    }
+
+    // sort_keys
+    let mut keys = Vec::new();
+    if let Some(sort_keys) = sort_keys {
+        for sort_key in sort_keys.iter() {
+            // ソートしたいカラムがあるか確認
+            if !df
+                .schema()
+                .has_column_with_unqualified_name(&sort_key.column)
+            {
+                Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "Invalid column for sorting in the specified sheet. column: {}",
+                        sort_key.column
+                    ))
+                    .into(),
+                ))?;
+            }
+
+            columns.insert(sort_key.column.clone());
+            keys.push(sort_key.clone());
+        }
+    }
+
+    // ソートキーが無ければプロファイルの順位で並べる．
+    // プロファイルも無ければ分散度を加味した順位で並べる
+    if sort_keys.is_none() {
+        if profile.is_some() {
+            keys.push(SortKey::asc("profile_rank"));
+        } else if rank_mode.is_some() {
+            keys.push(SortKey::asc("dispersion_rank"));
+        }
+    }
+
+    // パーティションによらず結果が決まるように，最後にrankで並べる
+    if df.schema().has_column_with_unqualified_name("rank") {
+        push_tiebreak(&mut keys, "rank");
+    }
+    let mut sort_exprs = keys.iter().map(SortKey::to_expr).collect::<Vec<_>>();
+
+    // rerank
+    // フィルター後の行をソート順(またはrerank_byの降順)で数え直す
+    if rerank || rerank_by.is_some() {
+        let rerank_exprs = match rerank_by {
+            Some(rerank_by) => {
+                if !df.schema().has_column_with_unqualified_name(rerank_by) {
+                    Err(Error::ArgError(
+                        CustomError::msg(format!(
+                            "Invalid column for reranking in the specified sheet. column: {rerank_by}"
+                        ))
+                        .into(),
+                    ))?;
+                }
+                columns.insert(rerank_by.to_string());
+
+                let mut rerank_exprs = vec![SortKey::desc(rerank_by).to_expr()];
+                rerank_exprs.extend(sort_exprs.iter().cloned());
+                rerank_exprs
+            }
+            None => sort_exprs.clone(),
+        };
+
+        df = df.with_column("subset_rank", row_number_expr(vec![], rerank_exprs))?;
+
+        // ソートキーが無ければ数え直した順位で並べる
+        if sort_keys.is_none() {
+            sort_exprs = vec![SortKey::asc("subset_rank").to_expr()];
+        }
+
+        if let Columns::List(list) = &mut columns {
+            list.shift_insert(0, "subset_rank".to_string());
+        }
+    }
+
+    // top_per
+    // ソートキーはグループ内の順位付けに使い，結果はグループとグループ内順位で並べる
+    if let Some((top, grouping)) = top_per {
+        df = top_per_group(df, top, grouping, sort_exprs)?;
+
+        let group_column = grouping.column_name();
+        sort_exprs = vec![
+            SortKey::asc(group_column.clone()).to_expr(),
+            SortKey::asc("group_rank").to_expr(),
+        ];
+
+        if let Columns::List(list) = &mut columns {
+            list.shift_insert(0, group_column);
+            list.shift_insert(1, "group_rank".to_string());
+            if let Grouping::Genre = grouping {
+                list.insert("genre_freq".to_string());
+            }
+        }
+    }
 
-        df = df.sort(vec![logical_expr::col(sorted_column).sort(false, false)])?;
+    if !sort_exprs.is_empty() {
+        df = df.sort(sort_exprs)?;
     }
 
     // skip and limit
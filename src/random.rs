@@ -0,0 +1,90 @@
+/// シードから再現できる擬似乱数生成器(SplitMix64)
+#[derive(Debug, Clone)]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// [0, 1)の一様乱数
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// [0, n)の一様な整数
+    pub(crate) fn next_index(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize % n.max(1)
+    }
+
+    /// 重みに比例した確率で位置を選ぶ．重みの合計が0なら一様に選ぶ．
+    pub(crate) fn choose_weighted(&mut self, weights: &[f64]) -> usize {
+        let total = weights.iter().sum::<f64>();
+        if total <= 0.0 {
+            return self.next_index(weights.len());
+        }
+        let mut threshold = self.next_f64() * total;
+        for (i, weight) in weights.iter().enumerate() {
+            if threshold < *weight {
+                return i;
+            }
+            threshold -= weight;
+        }
+        weights.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_splitmix64_reference() {
+        let mut random = Random::new(0);
+        assert_eq!(random.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(random.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(random.next_u64(), 0x06C4_5D18_8009_454F);
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Random::new(1).next_u64(), Random::new(2).next_u64());
+    }
+
+    #[test]
+    fn values_stay_in_range() {
+        let mut random = Random::new(7);
+        for _ in 0..1000 {
+            let value = random.next_f64();
+            assert!((0.0..1.0).contains(&value));
+            assert!(random.next_index(3) < 3);
+        }
+        assert_eq!(random.next_index(0), 0);
+    }
+
+    #[test]
+    fn choose_weighted_skips_zero_weights() {
+        let mut random = Random::new(3);
+        for _ in 0..1000 {
+            assert_eq!(random.choose_weighted(&[0.0, 2.0, 0.0]), 1);
+            assert_ne!(random.choose_weighted(&[1.0, 0.0, 1.0]), 1);
+        }
+        // 重みがすべて0なら一様に選ぶ
+        assert!(random.choose_weighted(&[0.0, 0.0]) < 2);
+    }
+}
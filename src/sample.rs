@@ -0,0 +1,159 @@
+use crate::random::Random;
+use crate::sort::SortKey;
+use crate::{CustomError, Error};
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{Array, Float64Array};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::cast::{as_float64_array, as_string_array};
+use datafusion::logical_expr;
+use datafusion::logical_expr::{create_udf, ColumnarValue, Volatility};
+use datafusion::prelude::{DataFrame, Expr};
+
+/// 抽出の優先度のカラム名
+pub const SAMPLE_KEY_COLUMN: &str = "sample_key";
+
+/// 行を識別するカラム．シートにあるものを全て使う．
+const KEY_COLUMNS: [&str; 5] = ["rank", "lemRank", "lemma", "word", "PoS"];
+
+/// 抽出の重み
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleWeight {
+    /// 頻度に比例
+    Freq,
+    /// 一様
+    Uniform,
+    /// 頻度の対数(ln(1 + freq))に比例
+    Log,
+}
+
+impl TryFrom<&str> for SampleWeight {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "freq" => Ok(Self::Freq),
+            "uniform" => Ok(Self::Uniform),
+            "log" => Ok(Self::Log),
+            _ => Err(CustomError::msg(
+                "Invalid sample weight. Choose weight in [\"freq\", \"uniform\", \"log\"]",
+            )),
+        }
+    }
+}
+
+/// 重み付きの無作為抽出
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub n: usize,
+    pub weight: SampleWeight,
+    pub seed: u64,
+}
+
+impl Sample {
+    /// 行から重みに比例した確率で`n`行を非復元抽出する．
+    ///
+    /// Efraimidis-Spirakisの方法で各行にln(u)/w(uは一様乱数，wは重み)の優先度を付け，
+    /// その大きい順に`n`行を残す．uはシードと行を識別するカラムの値から決めるので，
+    /// 同じシードと条件からは同じ行が選ばれる．重みが0以下やNULLの行は選ばれない．
+    pub fn apply(&self, df: DataFrame) -> Result<DataFrame, Error> {
+        let schema = df.schema();
+        let has_column = |column: &str| schema.has_column_with_unqualified_name(column);
+        let as_float = |column: &str| {
+            logical_expr::cast(
+                logical_expr::col(format!(r#""{column}""#)),
+                DataType::Float64,
+            )
+        };
+
+        let key_columns = KEY_COLUMNS
+            .into_iter()
+            .filter(|column| has_column(column))
+            .map(|column| {
+                logical_expr::cast(logical_expr::col(format!(r#""{column}""#)), DataType::Utf8)
+            })
+            .collect::<Vec<_>>();
+        if key_columns.is_empty() {
+            Err(Error::ArgError(
+                CustomError::msg("The specified sheet has no columns identifying rows.").into(),
+            ))?;
+        }
+        let key_expr = logical_expr::concat_ws(logical_expr::lit("\t"), key_columns);
+
+        let weight_expr = match self.weight {
+            SampleWeight::Uniform => logical_expr::lit(1.0),
+            SampleWeight::Freq | SampleWeight::Log => {
+                // 語形のシートはwordFreqが語形の頻度
+                let freq = match ["freq", "wordFreq"]
+                    .into_iter()
+                    .find(|column| has_column(column))
+                {
+                    Some(column) => as_float(column),
+                    None => Err(Error::ArgError(
+                        CustomError::msg("The specified sheet has no frequency column.").into(),
+                    ))?,
+                };
+                match self.weight {
+                    SampleWeight::Log => logical_expr::ln(freq + logical_expr::lit(1.0)),
+                    _ => freq,
+                }
+            }
+        };
+
+        let df = df
+            .with_column(
+                SAMPLE_KEY_COLUMN,
+                sample_key_expr(self.seed, key_expr, weight_expr),
+            )?
+            .filter(logical_expr::col(SAMPLE_KEY_COLUMN).is_not_null())?
+            .sort(vec![SortKey::desc(SAMPLE_KEY_COLUMN).to_expr()])?
+            .limit(0, Some(self.n))?;
+
+        Ok(df)
+    }
+}
+
+/// 行の識別子と重みから抽出の優先度を求めるUDFの式
+fn sample_key_expr(seed: u64, key: Expr, weight: Expr) -> Expr {
+    let seed = Random::new(seed).next_u64();
+    let sample_key = create_udf(
+        SAMPLE_KEY_COLUMN,
+        vec![DataType::Utf8, DataType::Float64],
+        Arc::new(DataType::Float64),
+        Volatility::Immutable,
+        Arc::new(move |args: &[ColumnarValue]| {
+            let rows = args
+                .iter()
+                .find_map(|arg| match arg {
+                    ColumnarValue::Array(array) => Some(array.len()),
+                    ColumnarValue::Scalar(_) => None,
+                })
+                .unwrap_or(1);
+            let keys = args[0].clone().into_array(rows)?;
+            let weights = args[1].clone().into_array(rows)?;
+
+            let priorities = as_string_array(&keys)?
+                .iter()
+                .zip(as_float64_array(&weights)?.iter())
+                .map(|(key, weight)| match (key, weight) {
+                    (Some(key), Some(weight)) if weight > 0.0 && weight.is_finite() => {
+                        // (0, 1]の一様乱数
+                        let u = 1.0 - Random::new(seed ^ fnv1a(key)).next_f64();
+                        Some(u.ln() / weight)
+                    }
+                    _ => None,
+                })
+                .collect::<Float64Array>();
+
+            Ok(ColumnarValue::Array(Arc::new(priorities)))
+        }),
+    );
+    sample_key.call(vec![key, weight])
+}
+
+/// 文字列の64bitのFNV-1aハッシュ．実行環境によらず同じ値になる．
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
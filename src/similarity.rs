@@ -0,0 +1,113 @@
+use crate::distribution::{Distance, DistributionSource, Distributions};
+use crate::{BandRange, CustomError, Error};
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// ジャンル(サブジャンル)の分布が近いレンマを距離の昇順で返す．
+///
+/// 各レンマの分布はジャンルごとの100万語あたりの頻度を合計1に正規化したベクトル．
+/// 対象のレンマが複数の品詞を持つ場合は`target_pos`で選び，指定がなければrankの最も
+/// 小さい行を用いる．候補は`pos_list`と`bands`で絞り込める．ジャンルの分布の場合は
+/// 候補の分布(%)もカラムとして加える．
+pub async fn similar_lemmas(
+    ctx: &SessionContext,
+    lemma: &str,
+    target_pos: Option<&str>,
+    source: DistributionSource,
+    distance: Distance,
+    pos_list: Option<&[String]>,
+    bands: Option<BandRange>,
+) -> Result<DataFrame, Error> {
+    let distributions = Distributions::load(ctx, source).await?;
+
+    let target = *distributions
+        .find(lemma, target_pos)
+        .first()
+        .ok_or_else(|| {
+            Error::ArgError(
+                CustomError::msg(format!("The lemma `{lemma}` is not found in the sheet.")).into(),
+            )
+        })?;
+    let target_profile = distributions.profile(target).ok_or_else(|| {
+        Error::ArgError(
+            CustomError::msg(format!(
+                "The lemma `{lemma}` has no frequency in any genre."
+            ))
+            .into(),
+        )
+    })?;
+
+    let mut neighbors = distributions
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(i, entry)| {
+            *i != target
+                && pos_list.map_or(true, |pos_list| pos_list.contains(&entry.pos))
+                && bands.map_or(true, |bands| bands.contains_rank(entry.rank))
+        })
+        .filter_map(|(i, _)| {
+            distributions
+                .profile(i)
+                .map(|profile| (i, distance.distance(&target_profile, &profile), profile))
+        })
+        .collect::<Vec<_>>();
+    neighbors.sort_by(|a, b| {
+        a.1.total_cmp(&b.1).then(
+            distributions.entries[a.0]
+                .rank
+                .cmp(&distributions.entries[b.0].rank),
+        )
+    });
+
+    let mut columns = vec![
+        (
+            "rank",
+            Arc::new(Int64Array::from_iter_values(
+                neighbors
+                    .iter()
+                    .map(|(i, _, _)| distributions.entries[*i].rank),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma",
+            Arc::new(StringArray::from_iter_values(
+                neighbors
+                    .iter()
+                    .map(|(i, _, _)| distributions.entries[*i].lemma.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "PoS",
+            Arc::new(StringArray::from_iter_values(
+                neighbors
+                    .iter()
+                    .map(|(i, _, _)| distributions.entries[*i].pos.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "distance",
+            Arc::new(Float64Array::from_iter_values(
+                neighbors.iter().map(|(_, distance, _)| *distance),
+            )) as ArrayRef,
+        ),
+    ];
+    if source == DistributionSource::Genre {
+        for (d, dimension) in distributions.dimensions.iter().enumerate() {
+            columns.push((
+                dimension.as_str(),
+                Arc::new(Float64Array::from_iter_values(
+                    neighbors.iter().map(|(_, _, profile)| profile[d] * 100.0),
+                )) as ArrayRef,
+            ));
+        }
+    }
+
+    let batch = RecordBatch::try_from_iter(columns).map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(ctx.read_batch(batch)?)
+}
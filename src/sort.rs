@@ -0,0 +1,192 @@
+use crate::{CustomError, Error};
+
+use datafusion::logical_expr;
+use datafusion::prelude::Expr;
+
+/// ソートのキー
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub column: String,
+    pub ascending: bool,
+    pub nulls_first: bool,
+}
+
+impl SortKey {
+    /// 昇順(NULLは末尾)
+    pub fn asc<S: Into<String>>(column: S) -> Self {
+        Self {
+            column: column.into(),
+            ascending: true,
+            nulls_first: false,
+        }
+    }
+    /// 降順(NULLは末尾)
+    pub fn desc<S: Into<String>>(column: S) -> Self {
+        Self {
+            column: column.into(),
+            ascending: false,
+            nulls_first: false,
+        }
+    }
+    /// DataFusionのソート式に変換する
+    pub fn to_expr(&self) -> Expr {
+        logical_expr::col(format!(r#""{}""#, self.column)).sort(self.ascending, self.nulls_first)
+    }
+}
+
+/// `--sort`の文字列をパースする．
+///
+/// `column [asc|desc] [nulls first|nulls last]`をカンマ区切りで並べる．
+/// 方向を省略した場合は昇順，NULLの位置を省略した場合は末尾とする．
+/// 同じカラムを複数回指定した場合はエラーとする．
+pub fn parse_sort_keys(source: &str) -> Result<Vec<SortKey>, Error> {
+    let mut keys = Vec::new();
+
+    for item in source.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            return Err(sort_error(source, "empty sort key."));
+        }
+
+        // クォートされたカラム名はスペースを含みうる
+        let (column, rest) = if let Some(stripped) = item.strip_prefix('"') {
+            match stripped.find('"') {
+                Some(end) => (stripped[..end].to_string(), &stripped[end + 1..]),
+                None => return Err(sort_error(source, "unterminated quotation.")),
+            }
+        } else {
+            match item.split_once(char::is_whitespace) {
+                Some((column, rest)) => (column.to_string(), rest),
+                None => (item.to_string(), ""),
+            }
+        };
+
+        let mut key = SortKey::asc(column);
+        let words = rest
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<_>>();
+
+        match words.iter().map(|word| word.as_str()).collect::<Vec<_>>()[..] {
+            [] => {}
+            ["asc"] => {}
+            ["desc"] => key.ascending = false,
+            ["nulls", "first"] => key.nulls_first = true,
+            ["nulls", "last"] => {}
+            ["asc", "nulls", "first"] => key.nulls_first = true,
+            ["asc", "nulls", "last"] => {}
+            ["desc", "nulls", "first"] => {
+                key.ascending = false;
+                key.nulls_first = true;
+            }
+            ["desc", "nulls", "last"] => key.ascending = false,
+            _ => {
+                return Err(sort_error(
+                    source,
+                    format!(
+                        "invalid sort key `{item}`. Use `column [asc|desc] [nulls first|last]`."
+                    ),
+                ))
+            }
+        }
+
+        if keys
+            .iter()
+            .any(|other: &SortKey| other.column == key.column)
+        {
+            return Err(sort_error(
+                source,
+                format!("duplicate sort key `{}`.", key.column),
+            ));
+        }
+
+        keys.push(key);
+    }
+
+    Ok(keys)
+}
+
+/// パーティションによらず結果が決まるように，ソートキーの末尾に`column`の昇順を加える．
+/// `column`が既にキーに含まれていれば何もしない．
+pub fn push_tiebreak(keys: &mut Vec<SortKey>, column: &str) {
+    if !keys.iter().any(|key| key.column == column) {
+        keys.push(SortKey::asc(column));
+    }
+}
+
+fn sort_error<D: std::fmt::Display>(source: &str, msg: D) -> Error {
+    Error::ArgError(
+        CustomError::msg(format!("Invalid sort specification `{source}`: {msg}")).into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direction_and_nulls_default_to_asc_nulls_last() {
+        assert_eq!(parse_sort_keys("freq").unwrap(), vec![SortKey::asc("freq")]);
+        assert_eq!(
+            parse_sort_keys("freq desc, lemma").unwrap(),
+            vec![SortKey::desc("freq"), SortKey::asc("lemma")]
+        );
+    }
+
+    #[test]
+    fn nulls_first_and_last() {
+        let keys =
+            parse_sort_keys("gloss nulls first, disp DESC NULLS LAST, freq desc nulls first")
+                .unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                SortKey {
+                    column: "gloss".to_string(),
+                    ascending: true,
+                    nulls_first: true,
+                },
+                SortKey::desc("disp"),
+                SortKey {
+                    column: "freq".to_string(),
+                    ascending: false,
+                    nulls_first: true,
+                },
+            ]
+        );
+        assert!(parse_sort_keys("freq nulls").is_err());
+        assert!(parse_sort_keys("freq desc first").is_err());
+    }
+
+    #[test]
+    fn quoted_columns_may_contain_spaces() {
+        assert_eq!(
+            parse_sort_keys(r#""tv/m freq" desc"#).unwrap(),
+            vec![SortKey::desc("tv/m freq")]
+        );
+        assert!(parse_sort_keys(r#""tv/m freq desc"#).is_err());
+    }
+
+    #[test]
+    fn duplicate_keys_are_rejected() {
+        let error = parse_sort_keys("freq desc, lemma, freq").unwrap_err();
+        assert!(error.to_string().contains("duplicate sort key `freq`."));
+        assert!(parse_sort_keys("freq,").is_err());
+    }
+
+    #[test]
+    fn rank_tiebreak_is_appended_once() {
+        let mut keys = parse_sort_keys("freq desc").unwrap();
+        push_tiebreak(&mut keys, "rank");
+        assert_eq!(keys, vec![SortKey::desc("freq"), SortKey::asc("rank")]);
+
+        // 明示されたrankの向きはそのまま残す
+        let mut keys = parse_sort_keys("rank desc").unwrap();
+        push_tiebreak(&mut keys, "rank");
+        assert_eq!(keys, vec![SortKey::desc("rank")]);
+
+        let mut keys = Vec::new();
+        push_tiebreak(&mut keys, "rank");
+        assert_eq!(keys, vec![SortKey::asc("rank")]);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{CustomError, Error, Genre, SheetType, BAND_SIZE};
+
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// 被覆率を求める閾値(%)
+pub const COVERAGE_THRESHOLDS: [u32; 4] = [80, 90, 95, 98];
+
+/// statsコマンドのグループ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsGroup {
+    /// 品詞ごと
+    Pos,
+    /// rankの1000語ごとの帯
+    Band,
+}
+
+impl TryFrom<&str> for StatsGroup {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "pos" => Ok(Self::Pos),
+            "band" => Ok(Self::Band),
+            _ => Err(CustomError::msg(
+                "Invalid group for stats. Choose group in [\"PoS\", \"band\"]",
+            )),
+        }
+    }
+}
+
+/// SQLで帯を求める式
+pub fn band_sql() -> String {
+    format!(r#"(CAST("rank" AS BIGINT) - 1) / {BAND_SIZE} + 1"#)
+}
+
+/// 登録済みのシートの統計量を求める．
+///
+/// 結果はセクション名とデータフレームの組のリスト．シートに存在するカラムに応じて
+/// 行数・頻度の分位点(summary)，品詞の分布(pos)，ジャンル別の合計(genres)，
+/// 頻度の上位から何語でトークンの80/90/95/98%を覆えるか(coverage)を返す．
+pub async fn sheet_stats(
+    ctx: &SessionContext,
+    sheet_type: SheetType,
+    group_by: Option<StatsGroup>,
+) -> Result<Vec<(String, DataFrame)>, Error> {
+    let table_name = sheet_type.table_name();
+    let schema = ctx.table(table_name.as_str()).await?.schema().clone();
+
+    let has_column = |column: &str| schema.has_column_with_unqualified_name(column);
+
+    // グループのカラムを追加したサブクエリ
+    let (source, group_column) = match group_by {
+        Some(StatsGroup::Pos) => {
+            if !has_column("PoS") {
+                Err(Error::ArgError(
+                    CustomError::msg("The specified sheet has no PoS column for grouping.").into(),
+                ))?;
+            }
+            (table_name.clone(), Some(r#""PoS""#.to_string()))
+        }
+        Some(StatsGroup::Band) => {
+            if !has_column("rank") {
+                Err(Error::ArgError(
+                    CustomError::msg("The specified sheet has no rank column for bands.").into(),
+                ))?;
+            }
+            (
+                format!("(SELECT *, {} AS band FROM {table_name})", band_sql()),
+                Some("band".to_string()),
+            )
+        }
+        None => (table_name.clone(), None),
+    };
+
+    let group_select = group_column
+        .as_ref()
+        .map(|column| format!("{column}, "))
+        .unwrap_or_default();
+    let group_clause = group_column
+        .as_ref()
+        .map(|column| format!(" GROUP BY {column} ORDER BY {column}"))
+        .unwrap_or_default();
+    let partition_clause = group_column
+        .as_ref()
+        .map(|column| format!("PARTITION BY {column} "))
+        .unwrap_or_default();
+
+    let mut sections = Vec::new();
+
+    // summary
+    {
+        let freq_stats = if has_column("freq") {
+            r#", SUM(freq) AS tokens, MIN(freq) AS freq_min,
+                APPROX_PERCENTILE_CONT(freq, 0.25) AS freq_q1,
+                APPROX_PERCENTILE_CONT(freq, 0.5) AS freq_median,
+                APPROX_PERCENTILE_CONT(freq, 0.75) AS freq_q3,
+                APPROX_PERCENTILE_CONT(freq, 0.9) AS freq_p90,
+                MAX(freq) AS freq_max, AVG(freq) AS freq_mean"#
+        } else {
+            ""
+        };
+        let sql = format!(
+            "SELECT {group_select}COUNT(*) AS row_count{freq_stats} FROM {source} t{group_clause}"
+        );
+        sections.push(("summary".to_string(), ctx.sql(&sql).await?));
+    }
+
+    // pos
+    if has_column("PoS") && group_by != Some(StatsGroup::Pos) {
+        // グループ内での割合を求める
+        let partition = partition_clause.trim_end();
+        let (tokens, tokens_percent) = if has_column("freq") {
+            (
+                ", SUM(freq) AS tokens".to_string(),
+                format!(", tokens * 100.0 / SUM(tokens) OVER ({partition}) AS tokens_percent"),
+            )
+        } else {
+            (String::new(), String::new())
+        };
+        let sql = format!(
+            r#"SELECT *,
+                row_count * 100.0 / SUM(row_count) OVER ({partition}) AS row_percent{tokens_percent}
+            FROM (
+                SELECT {group_select}"PoS", COUNT(*) AS row_count{tokens}
+                FROM {source} t
+                GROUP BY {group_select}"PoS"
+            ) p
+            ORDER BY {group_select}row_count DESC"#
+        );
+        sections.push(("pos".to_string(), ctx.sql(&sql).await?));
+    }
+
+    // genres
+    let genres = Genre::all()
+        .into_iter()
+        .filter(|genre| has_column(&genre.column_name()))
+        .collect::<Vec<_>>();
+    if !genres.is_empty() {
+        let sums = genres
+            .iter()
+            .map(|genre| format!(r#"SUM("{0}") AS "{0}""#, genre.column_name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!("SELECT {group_select}{sums} FROM {source} t{group_clause}");
+        sections.push(("genres".to_string(), ctx.sql(&sql).await?));
+    }
+
+    // coverage
+    if has_column("freq") {
+        let thresholds = COVERAGE_THRESHOLDS
+            .iter()
+            .map(|threshold| {
+                format!(
+                    "MIN(CASE WHEN cumulative >= total * {threshold} / 100.0 THEN n END) AS lemmas_{threshold}"
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            r#"WITH ordered AS (
+                SELECT {group_select}
+                    ROW_NUMBER() OVER ({partition_clause}ORDER BY freq DESC) AS n,
+                    SUM(freq) OVER ({partition_clause}ORDER BY freq DESC ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) AS cumulative,
+                    SUM(freq) OVER ({}) AS total
+                FROM {source} t
+            )
+            SELECT {group_select}COUNT(*) AS row_count, {thresholds} FROM ordered{group_clause}"#,
+            partition_clause.trim_end()
+        );
+        sections.push(("coverage".to_string(), ctx.sql(&sql).await?));
+    }
+
+    Ok(sections)
+}
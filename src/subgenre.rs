@@ -0,0 +1,305 @@
+use crate::{CustomError, Error, Genre, SheetType};
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::DFSchema;
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// サブジャンルのメタデータを登録するテーブル名
+pub const SUBGENRE_TABLE_NAME: &str = "subgenre_genres";
+
+/// サブジャンルのカラムと上位のジャンルの対応
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubgenreColumn {
+    /// subgenresシートのカラム名
+    pub column: String,
+    pub genre: Genre,
+    /// ジャンルの接頭辞を除いたサブジャンル名
+    pub subgenre: String,
+}
+
+/// カラム名からジャンルとサブジャンル名を求める．
+///
+/// `ACAD:History`のようにジャンルの接頭辞を持つカラムはそのジャンルに，
+/// サブジャンルに分かれていない`blog`のようなカラムはそれ自体のジャンルに属する．
+fn parse_subgenre_column(column: &str) -> Option<(Genre, String)> {
+    match column.split_once([':', '_', '-']) {
+        Some((prefix, subgenre)) => Genre::try_from(prefix)
+            .ok()
+            .map(|genre| (genre, subgenre.trim().to_string())),
+        None => Genre::try_from(column)
+            .ok()
+            .map(|genre| (genre, column.to_string())),
+    }
+}
+
+/// subgenresシートのスキーマからサブジャンルのカラムを抽出する
+pub fn subgenre_columns(schema: &DFSchema) -> Vec<SubgenreColumn> {
+    schema
+        .fields()
+        .iter()
+        .filter_map(|field| {
+            let column = field.name();
+            parse_subgenre_column(column).map(|(genre, subgenre)| SubgenreColumn {
+                column: column.to_owned(),
+                genre,
+                subgenre,
+            })
+        })
+        .collect()
+}
+
+/// 登録済みのsubgenresテーブルからサブジャンルのカラムを取得する
+pub async fn registered_subgenre_columns(
+    ctx: &SessionContext,
+) -> Result<Vec<SubgenreColumn>, Error> {
+    let df = ctx.table(SheetType::Second.table_name().as_str()).await?;
+    let columns = subgenre_columns(df.schema());
+
+    if columns.is_empty() {
+        Err(Error::DataFrameError(
+            CustomError::msg("No subgenre columns are found in the subgenres sheet.").into(),
+        ))?;
+    }
+
+    Ok(columns)
+}
+
+/// サブジャンルとジャンルの対応を`subgenre_genres(subgenre_column, genre, subgenre)`として登録する．
+/// サブジャンルのカラムが無いシートでは登録しない．
+pub async fn register_subgenre_metadata(ctx: &SessionContext) -> Result<(), Error> {
+    let df = ctx.table(SheetType::Second.table_name().as_str()).await?;
+    let columns = subgenre_columns(df.schema());
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "subgenre_column",
+            Arc::new(StringArray::from_iter_values(
+                columns.iter().map(|column| column.column.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "genre",
+            Arc::new(StringArray::from_iter_values(
+                columns.iter().map(|column| column.genre.column_name()),
+            )) as ArrayRef,
+        ),
+        (
+            "subgenre",
+            Arc::new(StringArray::from_iter_values(
+                columns.iter().map(|column| column.subgenre.clone()),
+            )) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    ctx.register_batch(SUBGENRE_TABLE_NAME, batch)?;
+
+    Ok(())
+}
+
+/// SQLの文字列リテラル
+pub(crate) fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// サブジャンルのカラムをNULLを0として参照するSQLの式
+pub(crate) fn subgenre_sql(column: &SubgenreColumn) -> String {
+    format!(
+        r#"COALESCE(CAST("{}" AS DOUBLE), 0.0)"#,
+        column.column.replace('"', "\"\"")
+    )
+}
+
+/// lemmaとPoSによる絞り込みの条件
+fn lemma_conditions(lemma: Option<&str>, pos_list: Option<&[String]>) -> String {
+    let mut conditions = Vec::new();
+    if let Some(lemma) = lemma {
+        conditions.push(format!("lemma = {}", quote_literal(lemma)));
+    }
+    if let Some(pos_list) = pos_list {
+        conditions.push(format!(
+            r#""PoS" IN ({})"#,
+            pos_list
+                .iter()
+                .map(|pos| quote_literal(pos))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+/// サブジャンルのカラムをジャンルごとに合計したテーブルを返す
+pub async fn rollup(ctx: &SessionContext, pos_list: Option<&[String]>) -> Result<DataFrame, Error> {
+    let columns = registered_subgenre_columns(ctx).await?;
+
+    let genre_sums = Genre::all()
+        .into_iter()
+        .filter_map(|genre| {
+            let sum = columns
+                .iter()
+                .filter(|column| column.genre == genre)
+                .map(subgenre_sql)
+                .collect::<Vec<_>>();
+            if sum.is_empty() {
+                None
+            } else {
+                Some(format!(
+                    r#"{} AS "{}""#,
+                    sum.join(" + "),
+                    genre.column_name()
+                ))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let sql = format!(
+        r#"SELECT "rank", lemma, "PoS", {genre_sums} FROM {}{} ORDER BY "rank""#,
+        SheetType::Second.table_name(),
+        lemma_conditions(None, pos_list)
+    );
+
+    Ok(ctx.sql(&sql).await?)
+}
+
+/// ジャンル内のサブジャンルを比較する縦持ちのテーブルを返す．
+///
+/// 各サブジャンルの頻度(freq)，サブジャンルの合計で正規化した100万語あたりの頻度(per_mil)，
+/// レンマのジャンル内の頻度に占める割合(share)を求める．
+pub async fn compare_within_genre(
+    ctx: &SessionContext,
+    genre: Genre,
+    lemma: Option<&str>,
+    pos_list: Option<&[String]>,
+) -> Result<DataFrame, Error> {
+    let columns = registered_subgenre_columns(ctx)
+        .await?
+        .into_iter()
+        .filter(|column| column.genre == genre)
+        .collect::<Vec<_>>();
+
+    if columns.is_empty() {
+        Err(Error::ArgError(
+            CustomError::msg(format!(
+                "No subgenre columns are found for the genre `{}`.",
+                genre.column_name()
+            ))
+            .into(),
+        ))?;
+    }
+
+    let totals = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("SUM({}) AS total_{i}", subgenre_sql(column)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let genre_freq = columns
+        .iter()
+        .map(subgenre_sql)
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let rows = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            format!(
+                r#"SELECT "rank", lemma, "PoS", {subgenre} AS subgenre,
+                    {freq} AS freq,
+                    {freq} * 1000000.0 / total_{i} AS per_mil,
+                    CASE WHEN genre_freq > 0 THEN {freq} * 100.0 / genre_freq END AS share
+                FROM filtered CROSS JOIN totals"#,
+                subgenre = quote_literal(&column.subgenre),
+                freq = subgenre_sql(column),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+
+    let table = SheetType::Second.table_name();
+    let sql = format!(
+        r#"WITH totals AS (
+            SELECT {totals} FROM {table}
+        ), filtered AS (
+            SELECT *, {genre_freq} AS genre_freq FROM {table}{conditions}
+        )
+        SELECT * FROM ({rows}) t ORDER BY "rank", per_mil DESC"#,
+        conditions = lemma_conditions(lemma, pos_list)
+    );
+
+    Ok(ctx.sql(&sql).await?)
+}
+
+/// レンマが相対的に多く使われるサブジャンルを返す．
+///
+/// 観測頻度と，レンマの総頻度をサブジャンルの大きさで按分した期待頻度の比(ratio)が
+/// `min_ratio`以上のサブジャンルをratioの降順で返す．
+pub async fn over_represented(
+    ctx: &SessionContext,
+    lemma: &str,
+    pos_list: Option<&[String]>,
+    min_ratio: f64,
+) -> Result<DataFrame, Error> {
+    let columns = registered_subgenre_columns(ctx).await?;
+
+    let totals = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("SUM({}) AS total_{i}", subgenre_sql(column)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let all_total = (0..columns.len())
+        .map(|i| format!("total_{i}"))
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let lemma_total = columns
+        .iter()
+        .map(subgenre_sql)
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let rows = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            format!(
+                r#"SELECT "rank", lemma, "PoS", {genre} AS genre, {subgenre} AS subgenre,
+                    {freq} AS freq,
+                    {freq} * 1000000.0 / total_{i} AS per_mil,
+                    lemma_total * total_{i} / ({all_total}) AS expected
+                FROM filtered CROSS JOIN totals"#,
+                genre = quote_literal(&column.genre.column_name()),
+                subgenre = quote_literal(&column.subgenre),
+                freq = subgenre_sql(column),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+
+    let table = SheetType::Second.table_name();
+    let sql = format!(
+        r#"WITH totals AS (
+            SELECT {totals} FROM {table}
+        ), filtered AS (
+            SELECT *, {lemma_total} AS lemma_total FROM {table}{conditions}
+        )
+        SELECT *, freq / expected AS ratio
+        FROM ({rows}) t
+        WHERE expected > 0 AND freq / expected >= {min_ratio}
+        ORDER BY "rank", ratio DESC"#,
+        conditions = lemma_conditions(Some(lemma), pos_list)
+    );
+
+    Ok(ctx.sql(&sql).await?)
+}
@@ -0,0 +1,73 @@
+/// 語から切り離す接語(`'s`, `'re`など)
+const CLITICS: [&str; 6] = ["s", "re", "ve", "ll", "d", "m"];
+
+/// 単語の内部に現れてもよい記号
+fn is_word_char(c: char) -> bool {
+    c.is_alphabetic() || c == '\'' || c == '’'
+}
+
+/// 英文を小文字の語に分割する．
+///
+/// COCAの語形に合わせて，`don't`は`do`と`n't`に，`it's`は`it`と`'s`に分ける．
+/// 数字を含む語やハイフンは区切りとして扱う．
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in text.split(|c: char| !is_word_char(c)) {
+        let word = word.replace('’', "'").to_lowercase();
+        let word = word.trim_matches('\'');
+        if word.is_empty() {
+            continue;
+        }
+
+        if let Some(stem) = word.strip_suffix("n't").filter(|stem| !stem.is_empty()) {
+            // can't -> ca n't, won't -> wo n't
+            tokens.push(stem.to_string());
+            tokens.push("n't".to_string());
+        } else if let Some((stem, clitic)) = word
+            .rsplit_once('\'')
+            .filter(|(stem, clitic)| !stem.is_empty() && CLITICS.contains(clitic))
+        {
+            tokens.push(stem.to_string());
+            tokens.push(format!("'{clitic}"));
+        } else {
+            tokens.push(word.to_string());
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_negative_contractions() {
+        assert_eq!(tokenize("Don't"), vec!["do", "n't"]);
+        assert_eq!(
+            tokenize("can't won\u{2019}t"),
+            vec!["ca", "n't", "wo", "n't"]
+        );
+    }
+
+    #[test]
+    fn splits_clitics() {
+        assert_eq!(
+            tokenize("It's what we've got, I'm sure."),
+            vec!["it", "'s", "what", "we", "'ve", "got", "i", "'m", "sure"]
+        );
+        // 接語でなければ分けない
+        assert_eq!(
+            tokenize("o'clock rock'n'roll"),
+            vec!["o'clock", "rock'n'roll"]
+        );
+    }
+
+    #[test]
+    fn digits_hyphens_and_quotes_are_separators() {
+        assert_eq!(
+            tokenize("'Well-known' 3rd edition"),
+            vec!["well", "known", "rd", "edition"]
+        );
+        assert!(tokenize("42 -- !").is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::query::{band_expr, row_number_expr};
+use crate::{CustomError, Error, Genre};
+
+use datafusion::arrow::datatypes::DataType;
+use datafusion::logical_expr;
+use datafusion::prelude::{DataFrame, Expr};
+
+/// `--per`で指定するグループ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Grouping {
+    /// 品詞ごと
+    Pos,
+    /// ジャンルのカラムごと(ジャンル別頻度で順位付け)
+    Genre,
+    /// rankの1000語ごとの帯
+    Band,
+    /// 任意のカラムの値ごと
+    Column(String),
+}
+
+impl Grouping {
+    /// 結果に含まれるグループのカラム名
+    pub fn column_name(&self) -> String {
+        match self {
+            Self::Pos => "PoS".to_string(),
+            Self::Genre => "genre".to_string(),
+            Self::Band => "band".to_string(),
+            Self::Column(column) => column.clone(),
+        }
+    }
+}
+
+impl TryFrom<&str> for Grouping {
+    type Error = CustomError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim() {
+            "" => Err(CustomError::msg("Empty value for grouping.")),
+            value if value.eq_ignore_ascii_case("pos") => Ok(Self::Pos),
+            value if value.eq_ignore_ascii_case("genre") => Ok(Self::Genre),
+            value if value.eq_ignore_ascii_case("band") => Ok(Self::Band),
+            value => Ok(Self::Column(value.to_string())),
+        }
+    }
+}
+
+/// グループごとに上位`top`件を抽出する．
+///
+/// グループ内の順位を`group_rank`カラムとして追加する．`order_by`はグループ内の並び順で，
+/// `Grouping::Genre`の場合はジャンル別頻度の降順の後のタイブレークとして用いる．
+pub fn top_per_group(
+    df: DataFrame,
+    top: usize,
+    grouping: &Grouping,
+    order_by: Vec<Expr>,
+) -> Result<DataFrame, Error> {
+    let group_rank_filter = logical_expr::col("group_rank").lt_eq(logical_expr::lit(top as u64));
+
+    match grouping {
+        Grouping::Genre => {
+            let genres = Genre::all()
+                .into_iter()
+                .filter(|genre| {
+                    df.schema()
+                        .has_column_with_unqualified_name(&genre.column_name())
+                })
+                .collect::<Vec<_>>();
+
+            if genres.is_empty() {
+                Err(Error::ArgError(
+                    CustomError::msg("The specified sheet has no genre columns.").into(),
+                ))?;
+            }
+
+            let mut result: Option<DataFrame> = None;
+            for genre in genres {
+                let genre_column = logical_expr::col(format!(r#""{}""#, genre.column_name()));
+
+                let mut genre_order_by = vec![genre_column.clone().sort(false, false)];
+                genre_order_by.extend(order_by.iter().cloned());
+
+                let genre_df = df
+                    .clone()
+                    .with_column("genre", logical_expr::lit(genre.column_name()))?
+                    .with_column(
+                        "genre_freq",
+                        logical_expr::cast(genre_column, DataType::Float64),
+                    )?
+                    .with_column("group_rank", row_number_expr(vec![], genre_order_by))?
+                    .filter(group_rank_filter.clone())?;
+
+                result = Some(match result {
+                    Some(result) => result.union(genre_df)?,
+                    None => genre_df,
+                });
+            }
+
+            Ok(result.unwrap())
+        }
+        Grouping::Pos | Grouping::Band | Grouping::Column(_) => {
+            let mut df = df;
+            if let Grouping::Band = grouping {
+                if !df.schema().has_column_with_unqualified_name("rank") {
+                    Err(Error::ArgError(
+                        CustomError::msg("The specified sheet has no rank column for bands.")
+                            .into(),
+                    ))?;
+                }
+                df = df.with_column("band", band_expr())?;
+            }
+
+            let group_column = grouping.column_name();
+            if !df.schema().has_column_with_unqualified_name(&group_column) {
+                Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "Invalid column for grouping in the specified sheet. column: {group_column}"
+                    ))
+                    .into(),
+                ))?;
+            }
+
+            let df = df
+                .with_column(
+                    "group_rank",
+                    row_number_expr(
+                        vec![logical_expr::col(format!(r#""{group_column}""#))],
+                        order_by,
+                    ),
+                )?
+                .filter(group_rank_filter)?;
+
+            Ok(df)
+        }
+    }
+}
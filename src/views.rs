@@ -0,0 +1,87 @@
+use crate::keyness::genre_sql;
+use crate::subgenre::{quote_literal, subgenre_columns, subgenre_sql};
+use crate::{Error, Genre, SheetType};
+
+use datafusion::prelude::SessionContext;
+
+/// ジャンル別頻度を縦持ちにしたビューの名前
+pub const LEMMA_GENRE_VIEW_NAME: &str = "lemma_genre";
+
+/// サブジャンル別頻度を縦持ちにしたビューの名前
+pub const LEMMA_SUBGENRE_VIEW_NAME: &str = "lemma_subgenre";
+
+/// lemmasシートのジャンルのカラムを`lemma_genre(rank, lemma, PoS, genre, freq, per_mil)`として登録する．
+///
+/// per_milはジャンルのカラムの合計で正規化した100万語あたりの頻度．
+pub async fn register_lemma_genre_view(ctx: &SessionContext) -> Result<(), Error> {
+    let table = SheetType::First.table_name();
+    let schema = ctx.table(table.as_str()).await?.schema().clone();
+
+    let genres = Genre::all()
+        .into_iter()
+        .filter(|genre| schema.has_column_with_unqualified_name(&genre.column_name()))
+        .collect::<Vec<_>>();
+    if genres.is_empty() {
+        return Ok(());
+    }
+
+    let totals = genres
+        .iter()
+        .enumerate()
+        .map(|(i, genre)| format!("SUM({}) AS total_{i}", genre_sql(*genre)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let rows = genres
+        .iter()
+        .enumerate()
+        .map(|(i, genre)| {
+            format!(
+                r#"SELECT "rank", lemma, "PoS", {name} AS genre, {freq} AS freq,
+                    {freq} * 1000000.0 / total_{i} AS per_mil
+                FROM {table} CROSS JOIN totals"#,
+                name = quote_literal(&genre.column_name()),
+                freq = genre_sql(*genre),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+
+    let sql = format!("WITH totals AS (SELECT {totals} FROM {table}) {rows}");
+    let df = ctx.sql(&sql).await?;
+
+    ctx.register_table(LEMMA_GENRE_VIEW_NAME, df.into_view())?;
+
+    Ok(())
+}
+
+/// subgenresシートのサブジャンルのカラムを
+/// `lemma_subgenre(rank, lemma, PoS, genre, subgenre, freq)`として登録する．
+/// サブジャンルのカラムが無いシートでは登録しない．
+pub async fn register_lemma_subgenre_view(ctx: &SessionContext) -> Result<(), Error> {
+    let table = SheetType::Second.table_name();
+    let columns = subgenre_columns(ctx.table(table.as_str()).await?.schema());
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let rows = columns
+        .iter()
+        .map(|column| {
+            format!(
+                r#"SELECT "rank", lemma, "PoS", {genre} AS genre, {subgenre} AS subgenre,
+                    {freq} AS freq
+                FROM {table}"#,
+                genre = quote_literal(&column.genre.column_name()),
+                subgenre = quote_literal(&column.subgenre),
+                freq = subgenre_sql(column),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+
+    let df = ctx.sql(&rows).await?;
+
+    ctx.register_table(LEMMA_SUBGENRE_VIEW_NAME, df.into_view())?;
+
+    Ok(())
+}
@@ -0,0 +1,565 @@
+use crate::distribution::LemmaEntry;
+use crate::document::Segment;
+use crate::known::KnownList;
+use crate::lemmatizer::Lemmatizer;
+use crate::text::tokenize;
+use crate::{BandRange, CustomError, Error};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// 文書中の語の出現
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    /// 小文字にした語形
+    pub word: String,
+    /// 対応するレンマ．リストに無い語はNone．
+    pub lemma: Option<LemmaEntry>,
+    /// 出現した部分の位置
+    pub segment: usize,
+}
+
+/// 文書を語に分割してレンマを引く
+pub fn lemmatize_segments(lemmatizer: &Lemmatizer, segments: &[Segment]) -> Vec<Occurrence> {
+    segments
+        .iter()
+        .enumerate()
+        .flat_map(|(segment, Segment { text, .. })| {
+            tokenize(text).into_iter().map(move |word| Occurrence {
+                lemma: lemmatizer.lemmatize(&word).cloned(),
+                word,
+                segment,
+            })
+        })
+        .collect()
+}
+
+/// レンマ(リストに無い語は語形)ごとの集計
+struct LemmaCount<'a> {
+    lemma: Option<&'a LemmaEntry>,
+    count: i64,
+    /// 最初の出現
+    first: &'a Occurrence,
+}
+
+/// 既知とする上位`known`語より順位の低いレンマを，rankの昇順で出現回数と最初の出現位置とともに返す．
+///
+/// `include_unlisted`がtrueならリストに無い語も最初の出現順に末尾へ加える．
+pub fn off_band_words(
+    ctx: &SessionContext,
+    segments: &[Segment],
+    occurrences: &[Occurrence],
+    known: usize,
+    include_unlisted: bool,
+) -> Result<DataFrame, Error> {
+    let mut counts: Vec<LemmaCount> = Vec::new();
+    let mut positions: HashMap<(Option<(&str, &str)>, &str), usize> = HashMap::new();
+    for occurrence in occurrences.iter() {
+        let lemma = occurrence.lemma.as_ref();
+        let key = match lemma {
+            Some(lemma) => (Some((lemma.lemma.as_str(), lemma.pos.as_str())), ""),
+            None => (None, occurrence.word.as_str()),
+        };
+        match positions.get(&key) {
+            Some(i) => counts[*i].count += 1,
+            None => {
+                positions.insert(key, counts.len());
+                counts.push(LemmaCount {
+                    lemma,
+                    count: 1,
+                    first: occurrence,
+                });
+            }
+        }
+    }
+
+    let mut counts = counts
+        .into_iter()
+        .filter(|count| match count.lemma {
+            Some(lemma) => lemma.rank > known as i64,
+            None => include_unlisted,
+        })
+        .collect::<Vec<_>>();
+    // リストに無い語は最初の出現順のまま末尾に置く
+    counts.sort_by_key(|count| count.lemma.map_or(i64::MAX, |lemma| lemma.rank));
+
+    let segment = |count: &LemmaCount| &segments[count.first.segment];
+
+    let mut columns = vec![
+        (
+            "rank",
+            Arc::new(Int64Array::from_iter(
+                counts
+                    .iter()
+                    .map(|count| count.lemma.map(|lemma| lemma.rank)),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma",
+            Arc::new(StringArray::from_iter_values(counts.iter().map(|count| {
+                count
+                    .lemma
+                    .map_or(count.first.word.clone(), |lemma| lemma.lemma.clone())
+            }))) as ArrayRef,
+        ),
+        (
+            "PoS",
+            Arc::new(StringArray::from_iter(
+                counts
+                    .iter()
+                    .map(|count| count.lemma.map(|lemma| lemma.pos.clone())),
+            )) as ArrayRef,
+        ),
+        (
+            "band",
+            Arc::new(Int64Array::from_iter(counts.iter().map(|count| {
+                count
+                    .lemma
+                    .map(|lemma| BandRange::band_of(lemma.rank) as i64)
+            }))) as ArrayRef,
+        ),
+        (
+            "count",
+            Arc::new(Int64Array::from_iter_values(
+                counts.iter().map(|count| count.count),
+            )) as ArrayRef,
+        ),
+        (
+            "first_form",
+            Arc::new(StringArray::from_iter_values(
+                counts.iter().map(|count| count.first.word.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "first_position",
+            Arc::new(StringArray::from_iter(
+                counts.iter().map(|count| segment(count).position.clone()),
+            )) as ArrayRef,
+        ),
+    ];
+    if segments.iter().any(|segment| segment.section.is_some()) {
+        columns.push((
+            "first_section",
+            Arc::new(StringArray::from_iter(
+                counts.iter().map(|count| segment(count).section.clone()),
+            )) as ArrayRef,
+        ));
+    }
+    columns.push((
+        "first_context",
+        Arc::new(StringArray::from_iter_values(
+            counts.iter().map(|count| segment(count).text.clone()),
+        )) as ArrayRef,
+    ));
+
+    let batch = RecordBatch::try_from_iter(columns).map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(ctx.read_batch(batch)?)
+}
+
+/// 全体の集計を表す`section`の値
+pub const ALL_SECTIONS: &str = "(all)";
+
+/// 頻度帯ごとのトークンの被覆率を章ごとと全体で返す．
+///
+/// 各行は章(section)と帯(band)ごとのトークン数，割合(percent)，帯1からの累積の割合
+/// (cumulative_percent)．リストに無い語は帯をNULLとして最後に置く．
+pub fn band_coverage(
+    ctx: &SessionContext,
+    segments: &[Segment],
+    occurrences: &[Occurrence],
+) -> Result<DataFrame, Error> {
+    // 章の出現順
+    let mut sections: Vec<Option<&str>> = Vec::new();
+    for segment in segments.iter() {
+        let section = segment.section.as_deref();
+        if section.is_some() && !sections.contains(&section) {
+            sections.push(section);
+        }
+    }
+
+    let mut section_column = Vec::new();
+    let mut band_column = Vec::new();
+    let mut tokens_column = Vec::new();
+    let mut percent_column = Vec::new();
+    let mut cumulative_column = Vec::new();
+
+    let mut push_section = |label: &str, section: Option<Option<&str>>| {
+        // 帯ごとのトークン数(Noneはリストに無い語)
+        let mut counts: BTreeMap<Option<usize>, i64> = BTreeMap::new();
+        for occurrence in occurrences.iter().filter(|occurrence| {
+            section.map_or(true, |section| {
+                segments[occurrence.segment].section.as_deref() == section
+            })
+        }) {
+            let band = occurrence
+                .lemma
+                .as_ref()
+                .map(|lemma| BandRange::band_of(lemma.rank));
+            *counts.entry(band).or_default() += 1;
+        }
+
+        let total = counts.values().sum::<i64>();
+        if total == 0 {
+            return;
+        }
+        // BTreeMapではNoneが先頭なので最後に回す
+        let unlisted = counts.remove(&None);
+        let mut cumulative = 0;
+        for (band, count) in counts
+            .into_iter()
+            .chain(unlisted.map(|count| (None, count)))
+        {
+            cumulative += count;
+            section_column.push(label.to_string());
+            band_column.push(band.map(|band| band as i64));
+            tokens_column.push(count);
+            percent_column.push(count as f64 * 100.0 / total as f64);
+            cumulative_column.push(cumulative as f64 * 100.0 / total as f64);
+        }
+    };
+
+    for section in sections.iter() {
+        push_section(section.unwrap_or_default(), Some(*section));
+    }
+    push_section(ALL_SECTIONS, None);
+
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "section",
+            Arc::new(StringArray::from(section_column)) as ArrayRef,
+        ),
+        ("band", Arc::new(Int64Array::from(band_column)) as ArrayRef),
+        (
+            "tokens",
+            Arc::new(Int64Array::from(tokens_column)) as ArrayRef,
+        ),
+        (
+            "percent",
+            Arc::new(Float64Array::from(percent_column)) as ArrayRef,
+        ),
+        (
+            "cumulative_percent",
+            Arc::new(Float64Array::from(cumulative_column)) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(ctx.read_batch(batch)?)
+}
+
+/// 上位`known`語のレンマを知っている学習者が理解できるトークンの割合(%)
+fn known_percent(occurrences: &[Occurrence], known: usize, unlisted_known: bool) -> f64 {
+    if occurrences.is_empty() {
+        return 0.0;
+    }
+    let known_tokens = occurrences
+        .iter()
+        .filter(|occurrence| match occurrence.lemma.as_ref() {
+            Some(lemma) => lemma.rank <= known as i64,
+            None => unlisted_known,
+        })
+        .count();
+    known_tokens as f64 * 100.0 / occurrences.len() as f64
+}
+
+/// 既知語彙の大きさによるテキストの被覆率を求める．
+///
+/// 結果はセクション名とデータフレームの組のリストで，上位`known`語での被覆率(summary)，
+/// `step`語ごとに`max_known`語までの被覆率の曲線(curve)，出現回数の多い未知のレンマ
+/// `top_unknown`語(unknown)を返す．`unlisted_known`がtrueならリストに無い語(固有名詞など)を
+/// 既知として数える．
+pub fn coverage_report(
+    ctx: &SessionContext,
+    occurrences: &[Occurrence],
+    known: usize,
+    step: usize,
+    max_known: usize,
+    unlisted_known: bool,
+    top_unknown: usize,
+) -> Result<Vec<(String, DataFrame)>, Error> {
+    let is_known = |occurrence: &Occurrence| match occurrence.lemma.as_ref() {
+        Some(lemma) => lemma.rank <= known as i64,
+        None => unlisted_known,
+    };
+    let unlisted_tokens = occurrences
+        .iter()
+        .filter(|occurrence| occurrence.lemma.is_none())
+        .count();
+    let known_tokens = occurrences
+        .iter()
+        .filter(|occurrence| is_known(occurrence))
+        .count();
+
+    let summary = RecordBatch::try_from_iter(vec![
+        (
+            "known",
+            Arc::new(Int64Array::from(vec![known as i64])) as ArrayRef,
+        ),
+        (
+            "tokens",
+            Arc::new(Int64Array::from(vec![occurrences.len() as i64])) as ArrayRef,
+        ),
+        (
+            "known_tokens",
+            Arc::new(Int64Array::from(vec![known_tokens as i64])) as ArrayRef,
+        ),
+        (
+            "unlisted_tokens",
+            Arc::new(Int64Array::from(vec![unlisted_tokens as i64])) as ArrayRef,
+        ),
+        (
+            "coverage",
+            Arc::new(Float64Array::from(vec![known_percent(
+                occurrences,
+                known,
+                unlisted_known,
+            )])) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    // curve
+    let steps = (1..=max_known / step.max(1))
+        .map(|i| i * step.max(1))
+        .collect::<Vec<_>>();
+    let curve = RecordBatch::try_from_iter(vec![
+        (
+            "known",
+            Arc::new(Int64Array::from_iter_values(
+                steps.iter().map(|known| *known as i64),
+            )) as ArrayRef,
+        ),
+        (
+            "coverage",
+            Arc::new(Float64Array::from_iter_values(
+                steps
+                    .iter()
+                    .map(|known| known_percent(occurrences, *known, unlisted_known)),
+            )) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    // unknown
+    let mut unknown_counts: Vec<(&LemmaEntry, i64)> = Vec::new();
+    let mut positions: HashMap<(&str, &str), usize> = HashMap::new();
+    for lemma in occurrences
+        .iter()
+        .filter(|occurrence| !is_known(occurrence))
+        .filter_map(|occurrence| occurrence.lemma.as_ref())
+    {
+        let key = (lemma.lemma.as_str(), lemma.pos.as_str());
+        match positions.get(&key) {
+            Some(i) => unknown_counts[*i].1 += 1,
+            None => {
+                positions.insert(key, unknown_counts.len());
+                unknown_counts.push((lemma, 1));
+            }
+        }
+    }
+    unknown_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.rank.cmp(&b.0.rank)));
+    unknown_counts.truncate(top_unknown);
+
+    let unknown = RecordBatch::try_from_iter(vec![
+        (
+            "rank",
+            Arc::new(Int64Array::from_iter_values(
+                unknown_counts.iter().map(|(lemma, _)| lemma.rank),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma",
+            Arc::new(StringArray::from_iter_values(
+                unknown_counts.iter().map(|(lemma, _)| lemma.lemma.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "PoS",
+            Arc::new(StringArray::from_iter_values(
+                unknown_counts.iter().map(|(lemma, _)| lemma.pos.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "count",
+            Arc::new(Int64Array::from_iter_values(
+                unknown_counts.iter().map(|(_, count)| *count),
+            )) as ArrayRef,
+        ),
+        (
+            "percent",
+            Arc::new(Float64Array::from_iter_values(unknown_counts.iter().map(
+                |(_, count)| *count as f64 * 100.0 / occurrences.len().max(1) as f64,
+            ))) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(vec![
+        ("summary".to_string(), ctx.read_batch(summary)?),
+        ("curve".to_string(), ctx.read_batch(curve)?),
+        ("unknown".to_string(), ctx.read_batch(unknown)?),
+    ])
+}
+
+/// `98%`(百分率)か`0.98`(0から1の割合)の形式の割合を%としてパースする．
+/// `%`の無い値は割合とするので，`98`のような値はエラーとする．
+pub fn parse_percent(source: &str) -> Result<f64, CustomError> {
+    let (value, is_percent) = match source.trim().strip_suffix('%') {
+        Some(value) => (value.trim(), true),
+        None => (source.trim(), false),
+    };
+    let value = value
+        .parse::<f64>()
+        .map_err(|e| CustomError::new(format!("Invalid percentage. value: {source}"), e))?;
+    if is_percent {
+        if !(0.0..=100.0).contains(&value) {
+            Err(CustomError::msg(format!(
+                "The percentage must be between 0% and 100%. value: {source}"
+            )))?;
+        }
+        Ok(value)
+    } else {
+        if !(0.0..=1.0).contains(&value) {
+            Err(CustomError::msg(format!(
+                "A value without `%` is a fraction between 0 and 1 (e.g. 0.98 or 98%). value: {source}"
+            )))?;
+        }
+        Ok(value * 100.0)
+    }
+}
+
+/// 目標の被覆率に達するために覚えるレンマを学習順に返す．
+///
+/// 上位`known`語と`known_list`のレンマを既知とし，残りのレンマをテキスト中の出現回数，
+/// 出現するテキストの数の降順，rankの昇順に並べて，累積の被覆率(coverage)が
+/// `target`%に達するまで選ぶ．各レンマのトークンは他のレンマと重ならないので，
+/// この貪欲法で選ぶ語数が最小になる．目標に届かない場合はリストのレンマを全て返す．
+pub fn study_plan(
+    ctx: &SessionContext,
+    texts: &[Vec<Occurrence>],
+    known: usize,
+    known_list: Option<&KnownList>,
+    target: f64,
+    unlisted_known: bool,
+) -> Result<DataFrame, Error> {
+    let is_known = |lemma: &LemmaEntry| {
+        lemma.rank <= known as i64 || known_list.map_or(false, |list| list.contains(lemma))
+    };
+
+    let total = texts.iter().map(|text| text.len()).sum::<usize>();
+    let mut known_tokens = 0;
+    // (レンマ, 出現回数, 出現するテキスト)
+    let mut candidates: Vec<(&LemmaEntry, i64, HashSet<usize>)> = Vec::new();
+    let mut positions: HashMap<(&str, &str), usize> = HashMap::new();
+    for (t, text) in texts.iter().enumerate() {
+        for occurrence in text.iter() {
+            match occurrence.lemma.as_ref() {
+                Some(lemma) if is_known(lemma) => known_tokens += 1,
+                Some(lemma) => {
+                    let key = (lemma.lemma.as_str(), lemma.pos.as_str());
+                    let i = *positions.entry(key).or_insert_with(|| {
+                        candidates.push((lemma, 0, HashSet::new()));
+                        candidates.len() - 1
+                    });
+                    candidates[i].1 += 1;
+                    candidates[i].2.insert(t);
+                }
+                None if unlisted_known => known_tokens += 1,
+                None => {}
+            }
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.1.cmp(&a.1)
+            .then(b.2.len().cmp(&a.2.len()))
+            .then(a.0.rank.cmp(&b.0.rank))
+    });
+
+    let percent = |tokens: usize| tokens as f64 * 100.0 / total.max(1) as f64;
+    let mut plan = Vec::new();
+    let mut covered = known_tokens;
+    for (lemma, count, text_set) in candidates.iter() {
+        if percent(covered) >= target {
+            break;
+        }
+        covered += *count as usize;
+        plan.push((*lemma, *count, text_set.len(), percent(covered)));
+    }
+
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "study_order",
+            Arc::new(Int64Array::from_iter_values(1..=plan.len() as i64)) as ArrayRef,
+        ),
+        (
+            "rank",
+            Arc::new(Int64Array::from_iter_values(
+                plan.iter().map(|(lemma, _, _, _)| lemma.rank),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma",
+            Arc::new(StringArray::from_iter_values(
+                plan.iter().map(|(lemma, _, _, _)| lemma.lemma.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "PoS",
+            Arc::new(StringArray::from_iter_values(
+                plan.iter().map(|(lemma, _, _, _)| lemma.pos.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "count",
+            Arc::new(Int64Array::from_iter_values(
+                plan.iter().map(|(_, count, _, _)| *count),
+            )) as ArrayRef,
+        ),
+        (
+            "texts",
+            Arc::new(Int64Array::from_iter_values(
+                plan.iter().map(|(_, _, texts, _)| *texts as i64),
+            )) as ArrayRef,
+        ),
+        (
+            "coverage",
+            Arc::new(Float64Array::from_iter_values(
+                plan.iter().map(|(_, _, _, coverage)| *coverage),
+            )) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(ctx.read_batch(batch)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_percent_accepts_percent_or_fraction() {
+        assert_eq!(parse_percent("98%").unwrap(), 98.0);
+        assert_eq!(parse_percent(" 95.5 % ").unwrap(), 95.5);
+        assert!((parse_percent("0.98").unwrap() - 98.0).abs() < 1e-9);
+        assert_eq!(parse_percent("1").unwrap(), 100.0);
+    }
+
+    #[test]
+    fn parse_percent_rejects_out_of_range_values() {
+        // `%`の無い98は割合として範囲外
+        let error = parse_percent("98").unwrap_err();
+        assert!(error.to_string().contains("fraction between 0 and 1"));
+
+        let error = parse_percent("101%").unwrap_err();
+        assert!(error.to_string().contains("between 0% and 100%"));
+
+        assert!(parse_percent("-0.5").is_err());
+        assert!(parse_percent("high").is_err());
+    }
+}
@@ -0,0 +1,429 @@
+use crate::batch::{f64_column, string_column};
+use crate::random::Random;
+use crate::subgenre::quote_literal;
+use crate::{BandRange, CustomError, Error, SheetType, BAND_SIZE};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::prelude::{DataFrame, SessionContext};
+
+/// 回答を記入するカラム名
+pub const RESPONSE_COLUMN: &str = "response";
+
+/// 帯の中で抽出の対象となったレンマの数のカラム名
+pub const BAND_SIZE_COLUMN: &str = "band_size";
+
+/// 頻度帯ごとに`per_band`語ずつレンマを抽出した語彙サイズテストを作る．
+///
+/// 同じシードと条件からは同じテストができる．帯の中の順序も無作為とし，
+/// 学習者が`response`カラムに知っている語は1，知らない語は0を記入する．
+/// `band_size`には品詞で絞り込んだ後の帯のレンマの数を残し，採点の際に用いる．
+pub async fn generate_test(
+    ctx: &SessionContext,
+    per_band: usize,
+    bands: BandRange,
+    pos_list: Option<&[String]>,
+    seed: u64,
+) -> Result<DataFrame, Error> {
+    let mut conditions = vec![format!(
+        r#""rank" BETWEEN {} AND {}"#,
+        (bands.start - 1) * BAND_SIZE + 1,
+        bands.end * BAND_SIZE
+    )];
+    if let Some(pos_list) = pos_list {
+        conditions.push(format!(
+            r#""PoS" IN ({})"#,
+            pos_list
+                .iter()
+                .map(|pos| quote_literal(pos))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    let sql = format!(
+        r#"SELECT "rank", lemma, "PoS" FROM {} WHERE {} ORDER BY "rank""#,
+        SheetType::First.table_name(),
+        conditions.join(" AND ")
+    );
+    let batches = ctx.sql(&sql).await?.collect().await?;
+    let ranks = f64_column(&batches, "rank")?;
+    let lemmas = string_column(&batches, "lemma")?;
+    let pos_values = string_column(&batches, "PoS")?;
+
+    // 帯ごとの候補
+    let mut candidates: BTreeMap<usize, Vec<(i64, String, String)>> = BTreeMap::new();
+    for ((rank, lemma), pos) in ranks.into_iter().zip(lemmas).zip(pos_values) {
+        let (Some(rank), Some(lemma)) = (rank, lemma) else {
+            continue;
+        };
+        let rank = rank.round() as i64;
+        candidates
+            .entry(BandRange::band_of(rank))
+            .or_default()
+            .push((rank, lemma, pos.unwrap_or_default()));
+    }
+
+    let items = draw_items(candidates, &bands, per_band, seed)?;
+
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "item",
+            Arc::new(Int64Array::from_iter_values(1..=items.len() as i64)) as ArrayRef,
+        ),
+        (
+            "band",
+            Arc::new(Int64Array::from_iter_values(
+                items.iter().map(|(band, _, _, _, _)| *band as i64),
+            )) as ArrayRef,
+        ),
+        (
+            BAND_SIZE_COLUMN,
+            Arc::new(Int64Array::from_iter_values(
+                items
+                    .iter()
+                    .map(|(_, band_size, _, _, _)| *band_size as i64),
+            )) as ArrayRef,
+        ),
+        (
+            "rank",
+            Arc::new(Int64Array::from_iter_values(
+                items.iter().map(|(_, _, rank, _, _)| *rank),
+            )) as ArrayRef,
+        ),
+        (
+            "lemma",
+            Arc::new(StringArray::from_iter_values(
+                items.iter().map(|(_, _, _, lemma, _)| lemma.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            "PoS",
+            Arc::new(StringArray::from_iter_values(
+                items.iter().map(|(_, _, _, _, pos)| pos.clone()),
+            )) as ArrayRef,
+        ),
+        (
+            RESPONSE_COLUMN,
+            Arc::new(StringArray::from_iter_values(items.iter().map(|_| ""))) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(ctx.read_batch(batch)?)
+}
+
+/// 抽出した項目(帯，帯のレンマの数，rank，レンマ，品詞)
+type Item = (usize, usize, i64, String, String);
+
+/// 帯ごとの候補から`per_band`語ずつ，部分的なFisher-Yatesのシャッフルで抽出する
+fn draw_items(
+    mut candidates: BTreeMap<usize, Vec<(i64, String, String)>>,
+    bands: &BandRange,
+    per_band: usize,
+    seed: u64,
+) -> Result<Vec<Item>, Error> {
+    let mut random = Random::new(seed);
+    let mut items = Vec::new();
+    for band in bands.bands() {
+        let mut pool = candidates.remove(&band).unwrap_or_default();
+        let band_size = pool.len();
+        if pool.len() < per_band {
+            Err(Error::ArgError(
+                CustomError::msg(format!(
+                    "The band {band} has only {} lemmas for {per_band} items.",
+                    pool.len()
+                ))
+                .into(),
+            ))?;
+        }
+        for i in 0..per_band {
+            let j = i + random.next_index(pool.len() - i);
+            pool.swap(i, j);
+        }
+        items.extend(
+            pool.into_iter()
+                .take(per_band)
+                .map(|(rank, lemma, pos)| (band, band_size, rank, lemma, pos)),
+        );
+    }
+    Ok(items)
+}
+
+/// 回答を知っている(true)か知らない(false)かに変換する．空欄は知らないとする．
+fn parse_response(value: &str) -> Result<bool, CustomError> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "y" | "yes" | "true" | "known" | "o" => Ok(true),
+        "0" | "n" | "no" | "false" | "unknown" | "x" | "" => Ok(false),
+        _ => Err(CustomError::msg(format!(
+            "Invalid response. Use 1 (known) or 0 (unknown). value: {value}"
+        ))),
+    }
+}
+
+/// 回答の`band`の値を帯の番号にする．1以上の整数でなければエラーとする．
+fn parse_band(value: f64, line: usize) -> Result<usize, Error> {
+    if value >= 1.0 && value.fract() == 0.0 {
+        Ok(value as usize)
+    } else {
+        Err(Error::ArgError(
+            CustomError::msg(format!(
+                "Invalid band in the response. Bands are integers from 1. line: {line}, value: {value}"
+            ))
+            .into(),
+        ))
+    }
+}
+
+/// 帯ごとの回答の集計
+#[derive(Debug, Clone, Default)]
+struct BandScore {
+    items: i64,
+    known: i64,
+    /// 帯の抽出の対象となったレンマの数
+    size: Option<f64>,
+}
+
+impl BandScore {
+    fn estimate(&self) -> f64 {
+        self.known as f64 / self.items as f64 * self.size.unwrap_or(BAND_SIZE as f64)
+    }
+}
+
+/// 回答済みのテストから語彙サイズを推定する．
+///
+/// 回答のcsvは`band`(無ければ`rank`から求める)と`response`のカラムを持つ．各帯の正答率に
+/// 帯の語数を掛けた値を帯ごとの推定値とし，その合計を語彙サイズとする．帯の語数は
+/// `band_size`カラム(品詞で絞り込んだ場合はその品詞のレンマの数)を用い，無ければ1000語とする．
+/// 結果は帯ごとの推定(bands)と全体の推定(summary)．
+pub fn score_test(
+    ctx: &SessionContext,
+    responses: &Path,
+) -> Result<Vec<(String, DataFrame)>, Error> {
+    let to_error = |e: csv::Error| {
+        Error::IOError(
+            CustomError::new(
+                format!(
+                    "Failed to read the responses. path: {}",
+                    responses.display()
+                ),
+                e,
+            )
+            .into(),
+        )
+    };
+    let mut reader = csv::Reader::from_path(responses).map_err(to_error)?;
+    let headers = reader.headers().map_err(to_error)?.clone();
+    let position = |name: &str| headers.iter().position(|header| header.trim() == name);
+
+    let response_position = position(RESPONSE_COLUMN).ok_or_else(|| {
+        Error::ArgError(CustomError::msg("The responses have no response column.").into())
+    })?;
+    let band_position = position("band");
+    let band_size_position = position(BAND_SIZE_COLUMN);
+    let rank_position = position("rank");
+    if band_position.is_none() && rank_position.is_none() {
+        Err(Error::ArgError(
+            CustomError::msg("The responses have neither band nor rank column.").into(),
+        ))?;
+    }
+
+    let mut counts: BTreeMap<usize, BandScore> = BTreeMap::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(to_error)?;
+        let parse_number = |position: Option<usize>| {
+            position
+                .and_then(|position| record.get(position))
+                .and_then(|value| value.trim().parse::<f64>().ok())
+        };
+        let band = match parse_number(band_position) {
+            Some(band) => parse_band(band, line + 2)?,
+            None => match parse_number(rank_position) {
+                Some(rank) => BandRange::band_of(rank as i64),
+                None => Err(Error::ArgError(
+                    CustomError::msg(format!(
+                        "No band or rank in the response. line: {}",
+                        line + 2
+                    ))
+                    .into(),
+                ))?,
+            },
+        };
+        let known = parse_response(record.get(response_position).unwrap_or_default())
+            .map_err(|e| Error::ArgError(e.into()))?;
+
+        let score = counts.entry(band).or_default();
+        score.items += 1;
+        score.known += known as i64;
+        if score.size.is_none() {
+            score.size = parse_number(band_size_position).filter(|size| *size > 0.0);
+        }
+    }
+    if counts.is_empty() {
+        Err(Error::ArgError(
+            CustomError::msg("The responses have no items.").into(),
+        ))?;
+    }
+
+    let estimates = counts.values().map(BandScore::estimate).collect::<Vec<_>>();
+
+    let bands = RecordBatch::try_from_iter(vec![
+        (
+            "band",
+            Arc::new(Int64Array::from_iter_values(
+                counts.keys().map(|band| *band as i64),
+            )) as ArrayRef,
+        ),
+        (
+            "items",
+            Arc::new(Int64Array::from_iter_values(
+                counts.values().map(|score| score.items),
+            )) as ArrayRef,
+        ),
+        (
+            "known",
+            Arc::new(Int64Array::from_iter_values(
+                counts.values().map(|score| score.known),
+            )) as ArrayRef,
+        ),
+        (
+            "percent",
+            Arc::new(Float64Array::from_iter_values(
+                counts
+                    .values()
+                    .map(|score| score.known as f64 * 100.0 / score.items as f64),
+            )) as ArrayRef,
+        ),
+        (
+            BAND_SIZE_COLUMN,
+            Arc::new(Float64Array::from_iter_values(
+                counts
+                    .values()
+                    .map(|score| score.size.unwrap_or(BAND_SIZE as f64)),
+            )) as ArrayRef,
+        ),
+        (
+            "estimate",
+            Arc::new(Float64Array::from(estimates.clone())) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    let summary = RecordBatch::try_from_iter(vec![
+        (
+            "bands",
+            Arc::new(Int64Array::from(vec![counts.len() as i64])) as ArrayRef,
+        ),
+        (
+            "items",
+            Arc::new(Int64Array::from(vec![counts
+                .values()
+                .map(|score| score.items)
+                .sum::<i64>()])) as ArrayRef,
+        ),
+        (
+            "known",
+            Arc::new(Int64Array::from(vec![counts
+                .values()
+                .map(|score| score.known)
+                .sum::<i64>()])) as ArrayRef,
+        ),
+        (
+            "vocabulary_size",
+            Arc::new(Float64Array::from(vec![estimates.iter().sum::<f64>()])) as ArrayRef,
+        ),
+    ])
+    .map_err(|e| Error::DataFrameError(e.into()))?;
+
+    Ok(vec![
+        ("summary".to_string(), ctx.read_batch(summary)?),
+        ("bands".to_string(), ctx.read_batch(bands)?),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates() -> BTreeMap<usize, Vec<(i64, String, String)>> {
+        let mut candidates = BTreeMap::new();
+        for rank in 1..=40_i64 {
+            candidates
+                .entry(BandRange::band_of(rank * 100))
+                .or_insert_with(Vec::new)
+                .push((rank * 100, format!("lemma{rank}"), "n".to_string()));
+        }
+        candidates
+    }
+
+    #[test]
+    fn draw_items_is_deterministic_by_seed() {
+        let bands = BandRange { start: 1, end: 3 };
+        let a = draw_items(candidates(), &bands, 4, 7).unwrap();
+        let b = draw_items(candidates(), &bands, 4, 7).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, draw_items(candidates(), &bands, 4, 8).unwrap());
+
+        // 帯の順に並び，各帯から重複なく抽出する
+        assert_eq!(a.len(), 12);
+        for (i, band) in (1..=3).enumerate() {
+            let items = &a[i * 4..(i + 1) * 4];
+            assert!(items.iter().all(|item| item.0 == band && item.1 == 10));
+            let mut ranks = items.iter().map(|item| item.2).collect::<Vec<_>>();
+            ranks.sort();
+            ranks.dedup();
+            assert_eq!(ranks.len(), 4);
+        }
+    }
+
+    #[test]
+    fn draw_items_rejects_small_bands() {
+        let bands = BandRange { start: 4, end: 5 };
+        assert!(draw_items(candidates(), &bands, 5, 0).is_err());
+    }
+
+    #[test]
+    fn parse_response_tokens() {
+        for value in ["1", "y", "Yes", "true", "known", "o", " 1 "] {
+            assert!(parse_response(value).unwrap(), "{value}");
+        }
+        for value in ["0", "n", "NO", "false", "unknown", "x", ""] {
+            assert!(!parse_response(value).unwrap(), "{value}");
+        }
+        assert!(parse_response("maybe")
+            .unwrap_err()
+            .to_string()
+            .contains("value: maybe"));
+    }
+
+    #[test]
+    fn parse_band_accepts_positive_integers() {
+        assert_eq!(parse_band(3.0, 2).unwrap(), 3);
+        for value in [0.0, -1.0, 0.5, 2.5, f64::NAN, f64::INFINITY] {
+            assert!(parse_band(value, 5)
+                .unwrap_err()
+                .to_string()
+                .contains("line: 5"));
+        }
+    }
+
+    #[test]
+    fn estimate_scales_by_band_size() {
+        let score = BandScore {
+            items: 20,
+            known: 15,
+            size: None,
+        };
+        assert_eq!(score.estimate(), 0.75 * BAND_SIZE as f64);
+
+        // 品詞で絞り込んだ帯はその帯のレンマの数で推定する
+        let score = BandScore {
+            size: Some(400.0),
+            ..score
+        };
+        assert_eq!(score.estimate(), 300.0);
+    }
+}